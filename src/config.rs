@@ -0,0 +1,174 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use crate::grid::GridCellSize;
+use crate::io::{CollisionMode, OutputFormat, TextureEncoding, TextureFormat, WriteOptions};
+use crate::model::{CutMode, DistanceTest, IslandThreshold, NormalMode, OverlapThreshold};
+use crate::world::HqTieBreak;
+
+/// Folders of assets sharing a priority, with the options for cleaning them
+/// against the layers above.
+#[derive(Debug, Clone)]
+pub struct AssetLayer {
+    /// Folders containing the assets of the layer
+    pub folders: Vec<OsString>,
+    /// Distance from the assets of higher layers within which vertices of
+    /// this layer are overlapping. For the top layer only used with
+    /// [`Config::hq_tie_break`].
+    pub overlap_threshold: OverlapThreshold,
+    /// Factor the textures of the layer are downscaled by, 1 copies them
+    pub texture_downscale: u32,
+}
+
+/// Input and output locations and processing options for a cleaning run.
+/// Start from [`Config::default`], which has the defaults of the command
+/// line, and set the fields needed.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// Folders containing the hq assets. Ignored when [`Self::layers`] is
+    /// set.
+    pub hq_asset_folders: Vec<OsString>,
+    /// Folder containing the normal assets. Ignored when [`Self::layers`]
+    /// is set.
+    pub normal_asset_folder: OsString,
+    /// Asset layers from the highest priority to the lowest. Each layer is
+    /// cleaned against all layers above it as they are written, and the top
+    /// layer is copied as it is. When empty, the hq assets and the normal
+    /// assets form the two layers.
+    pub layers: Vec<AssetLayer>,
+    /// Also clean the hq assets, the top layer, against each other. Of two
+    /// overlapping hq assets the one winning the tie-break keeps the
+    /// overlapping geometry.
+    pub hq_tie_break: Option<HqTieBreak>,
+    /// Folder where the results are written
+    pub out_folder: OsString,
+    /// Globs of the assets to use from the input folders, all when empty.
+    /// Globs without a `/` are matched against file names, others against
+    /// the path relative to the input folder.
+    pub include: Vec<String>,
+    /// Globs of the assets and subfolders to skip in the input folders
+    pub exclude: Vec<String>,
+    /// How assets that would be written to the same output path are told
+    /// apart
+    pub collision_mode: CollisionMode,
+    /// How the overlapping geometry is removed
+    pub cut_mode: CutMode,
+    /// Bridge the seams left by the removed geometry to the hq assets
+    pub stitch_seams: bool,
+    /// How the normals of the rewritten assets are produced
+    pub normal_mode: NormalMode,
+    /// Distance from the hq assets within which normal asset vertices are
+    /// overlapping
+    pub overlap_threshold: OverlapThreshold,
+    /// How the distance to the hq triangles is measured
+    pub distance_test: DistanceTest,
+    /// Size below which islands left between overlapping vertices are
+    /// removed
+    pub island_threshold: IslandThreshold,
+    /// Cell size of the index grids of the hq assets
+    pub grid_cell_size: GridCellSize,
+    /// File format of the written assets
+    pub output_format: OutputFormat,
+    /// Store the textures inside the written GLB files
+    pub embed_textures: bool,
+    /// Factor the textures of the hq assets are downscaled by, 1 copies them
+    pub hq_texture_downscale: u32,
+    /// Factor the textures of the normal assets are downscaled by, 1 copies
+    /// them
+    pub normal_texture_downscale: u32,
+    /// Downscale factors for the assets in a folder and its subfolders,
+    /// overriding the hq and normal factors. The deepest matching folder
+    /// wins.
+    pub texture_downscale_overrides: Vec<(OsString, u32)>,
+    /// File format the textures are written as
+    pub texture_format: TextureFormat,
+    /// Quality of textures written as JPEG, 1-100
+    pub jpeg_quality: u8,
+    /// Crop the textures of the rewritten assets to the region their
+    /// remaining triangles use
+    pub crop_textures: bool,
+    /// Only report what would be removed, without writing anything
+    pub dry_run: bool,
+    /// Folder for copies of the normal assets with the vertices coloured by
+    /// how they were classified
+    pub debug_folder: Option<OsString>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hq_asset_folders: vec![],
+            normal_asset_folder: OsString::new(),
+            layers: vec![],
+            hq_tie_break: None,
+            out_folder: OsString::new(),
+            include: vec![],
+            exclude: vec![],
+            collision_mode: CollisionMode::default(),
+            cut_mode: CutMode::default(),
+            stitch_seams: false,
+            normal_mode: NormalMode::default(),
+            overlap_threshold: OverlapThreshold::default(),
+            distance_test: DistanceTest::default(),
+            island_threshold: IslandThreshold::default(),
+            grid_cell_size: GridCellSize::default(),
+            output_format: OutputFormat::default(),
+            embed_textures: false,
+            hq_texture_downscale: 1,
+            normal_texture_downscale: 2,
+            texture_downscale_overrides: vec![],
+            texture_format: TextureFormat::default(),
+            jpeg_quality: TextureEncoding::default().jpeg_quality,
+            crop_textures: false,
+            dry_run: false,
+            debug_folder: None,
+        }
+    }
+}
+
+impl Config {
+    /// The layers of the run, see [`Self::layers`]
+    pub fn asset_layers(&self) -> Vec<AssetLayer> {
+        if !self.layers.is_empty() {
+            return self.layers.clone();
+        }
+
+        vec![
+            AssetLayer {
+                folders: self.hq_asset_folders.clone(),
+                overlap_threshold: self.overlap_threshold,
+                texture_downscale: self.hq_texture_downscale,
+            },
+            AssetLayer {
+                folders: vec![self.normal_asset_folder.clone()],
+                overlap_threshold: self.overlap_threshold,
+                texture_downscale: self.normal_texture_downscale,
+            },
+        ]
+    }
+
+    /// Factor the textures of source_file in layer are downscaled by
+    pub fn texture_downscale_factor(&self, source_file: &OsStr, layer: &AssetLayer) -> u32 {
+        let source_file = Path::new(source_file);
+        self.texture_downscale_overrides
+            .iter()
+            .filter(|(folder, _)| source_file.starts_with(folder))
+            .max_by_key(|(folder, _)| Path::new(folder).components().count())
+            .map(|(_, factor)| *factor)
+            .unwrap_or(layer.texture_downscale)
+    }
+
+    pub fn write_options(&self) -> WriteOptions {
+        WriteOptions {
+            format: self.output_format,
+            embed_textures: self.embed_textures,
+            texture_encoding: TextureEncoding {
+                format: self.texture_format,
+                jpeg_quality: self.jpeg_quality,
+            },
+        }
+    }
+}
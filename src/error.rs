@@ -0,0 +1,88 @@
+use std::{error::Error, ffi::OsString, fmt, path::PathBuf};
+
+/// Errors that can occur while loading, processing or writing assets.
+#[derive(Debug)]
+pub enum CleanerError {
+    /// Loading an OBJ file or its materials failed
+    Load {
+        path: OsString,
+        source: tobj::LoadError,
+    },
+    /// Loading a glTF file failed
+    Gltf { path: OsString, source: gltf::Error },
+    /// A filesystem operation failed
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Decoding, resizing or encoding a texture failed
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// A material references a texture that doesn't exist
+    MissingTexture(PathBuf),
+    /// The path doesn't have a parent folder or a file name
+    InvalidPath(OsString),
+    /// An include or exclude glob can't be parsed
+    InvalidPattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+    /// The mesh uses a position or index layout that isn't supported
+    UnsupportedMesh(&'static str),
+    /// The asset or mesh has no triangles
+    EmptyMesh,
+    /// A mesh lacks data a processing step needs, e.g. the index grid of an
+    /// hq mesh
+    MissingMeshData(&'static str),
+}
+
+impl CleanerError {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for CleanerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load { path, source } => write!(f, "Failed loading {path:?}: {source}"),
+            Self::Gltf { path, source } => write!(f, "Failed loading {path:?}: {source}"),
+            Self::Io { path, source } => write!(f, "I/O error on {path:?}: {source}"),
+            Self::Image { path, source } => write!(f, "Image error on {path:?}: {source}"),
+            Self::MissingTexture(path) => write!(f, "Unable to load texture: {path:?}"),
+            Self::InvalidPath(path) => write!(f, "Invalid asset path: {path:?}"),
+            Self::InvalidPattern { pattern, source } => {
+                write!(f, "Invalid pattern {pattern:?}: {source}")
+            }
+            Self::UnsupportedMesh(reason) => write!(f, "Unsupported mesh: {reason}"),
+            Self::EmptyMesh => write!(f, "No triangles"),
+            Self::MissingMeshData(data) => write!(f, "Mesh without {data}"),
+        }
+    }
+}
+
+impl Error for CleanerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Load { source, .. } => Some(source),
+            Self::Gltf { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            Self::Image { source, .. } => Some(source),
+            Self::InvalidPattern { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// An asset that couldn't be processed. The rest of the run continues
+/// without it.
+#[derive(Debug)]
+pub struct AssetFailure {
+    pub path: OsString,
+    pub error: CleanerError,
+}
@@ -0,0 +1,612 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use serde_json::{Value, json};
+use three_d_asset::{
+    Indices, InnerSpace, Mat4, Matrix, Matrix3, Positions, SquareMatrix, TriMesh, Vec3, Vector2,
+};
+use tobj::Material as TobjMaterial;
+
+use crate::error::CleanerError;
+use crate::model::{LoadedMeshes, MeshNames, Model, indices_u32, positions_f32};
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4e4f_534a;
+const GLB_CHUNK_BIN: u32 = 0x004e_4942;
+
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Meshes, the material of each mesh and the encoded images embedded in the
+/// file, by the texture name used in the materials
+pub type LoadedGltf = (LoadedMeshes, HashMap<String, Vec<u8>>);
+
+/// Image of a texture in a GLB file
+pub enum GlbImage {
+    /// Image stored next to the GLB file
+    External(String),
+    /// Encoded image stored in the binary chunk
+    Embedded { data: Vec<u8>, mime_type: String },
+}
+
+fn gltf_error(path: &OsStr) -> impl Fn(gltf::Error) -> CleanerError + '_ {
+    move |source| CleanerError::Gltf {
+        path: path.to_os_string(),
+        source,
+    }
+}
+
+/// Converts a glTF material to the tobj material used for all assets.
+/// Roughness and metalness are stored as the `Pr` and `Pm` MTL parameters.
+fn gltf_material_to_tobj(material: &gltf::Material, image_names: &[String]) -> TobjMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let base_color = pbr.base_color_factor();
+
+    let name = match (material.name(), material.index()) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(index)) => format!("material_{index}"),
+        (None, None) => "default".to_string(),
+    };
+
+    let image_name = |texture: gltf::Texture| image_names[texture.source().index()].clone();
+
+    let mut tobj_material = TobjMaterial {
+        name,
+        diffuse: Some([base_color[0], base_color[1], base_color[2]]),
+        dissolve: Some(base_color[3]),
+        diffuse_texture: pbr
+            .base_color_texture()
+            .map(|info| image_name(info.texture())),
+        normal_texture: material
+            .normal_texture()
+            .map(|normal| image_name(normal.texture())),
+        ..Default::default()
+    };
+
+    tobj_material
+        .unknown_param
+        .insert("Pr".to_string(), pbr.roughness_factor().to_string());
+    tobj_material
+        .unknown_param
+        .insert("Pm".to_string(), pbr.metallic_factor().to_string());
+
+    tobj_material
+}
+
+/// Decodes a base64 `data:` URI to its media type and data
+fn decode_data_uri(uri: &str) -> Result<(&str, Vec<u8>), gltf::Error> {
+    let (media_type, data) = uri
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or(gltf::Error::UnsupportedScheme)?;
+    let data = base64::decode(data).map_err(gltf::Error::Base64)?;
+    Ok((media_type, data))
+}
+
+/// Returns the name used for each image in the materials. Embedded images,
+/// in buffer views or data URIs, are named after the source file and
+/// collected to embedded_textures.
+fn collect_images(
+    path: &Path,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    embedded_textures: &mut HashMap<String, Vec<u8>>,
+) -> Result<Vec<String>, CleanerError> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut embed = |index: usize, mime_type: Option<&str>, data: Vec<u8>| {
+        let extension = match mime_type {
+            Some("image/jpeg") => "jpg",
+            _ => "png",
+        };
+        let name = format!("{stem}_image_{index}.{extension}");
+        embedded_textures.insert(name.clone(), data);
+        name
+    };
+
+    document
+        .images()
+        .map(|image| match image.source() {
+            gltf::image::Source::View { view, mime_type } => {
+                let buffer = &buffers[view.buffer().index()];
+                let data = buffer[view.offset()..view.offset() + view.length()].to_vec();
+                Ok(embed(image.index(), Some(mime_type), data))
+            }
+            gltf::image::Source::Uri { uri, mime_type } if uri.starts_with("data:") => {
+                let (media_type, data) =
+                    decode_data_uri(uri).map_err(gltf_error(path.as_os_str()))?;
+                let mime_type = mime_type.or(Some(media_type).filter(|m| !m.is_empty()));
+                Ok(embed(image.index(), mime_type, data))
+            }
+            gltf::image::Source::Uri { uri, .. } => Ok(uri.to_string()),
+        })
+        .collect()
+}
+
+fn primitive_to_trimesh(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    transform: &Mat4,
+) -> Option<TriMesh> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        return None;
+    }
+
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions = reader
+        .read_positions()?
+        .map(|p| (transform * Vec3::from(p).extend(1.0)).truncate())
+        .collect::<Vec<_>>();
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    let normal_transform = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    )
+    .invert()
+    .map(|m| m.transpose())
+    .unwrap_or_else(Matrix3::identity);
+
+    let normals = reader.read_normals().map(|normals| {
+        normals
+            .map(|n| (normal_transform * Vec3::from(n)).normalize())
+            .collect()
+    });
+
+    // glTF has the uv origin at the top left corner, OBJ at the bottom left
+    let uvs = reader.read_tex_coords(0).map(|uvs| {
+        uvs.into_f32()
+            .map(|uv| Vector2::new(uv[0], 1.0 - uv[1]))
+            .collect()
+    });
+
+    Some(TriMesh {
+        positions: Positions::F32(positions),
+        indices: Indices::U32(indices),
+        uvs,
+        normals,
+        tangents: None,
+        colors: None,
+    })
+}
+
+fn collect_node_meshes(
+    node: &gltf::Node,
+    parent_transform: &Mat4,
+    buffers: &[gltf::buffer::Data],
+    image_names: &[String],
+    out: &mut LoadedMeshes,
+) {
+    let transform = parent_transform * Mat4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        // Nodes are the objects and meshes the groups of the OBJ output
+        let names = MeshNames {
+            object: node.name().map(str::to_string),
+            group: mesh.name().map(str::to_string),
+        };
+        for primitive in mesh.primitives() {
+            let Some(trimesh) = primitive_to_trimesh(&primitive, buffers, &transform) else {
+                continue;
+            };
+            let material = gltf_material_to_tobj(&primitive.material(), image_names);
+            out.push((trimesh, material, names.clone()));
+        }
+    }
+
+    for child in node.children() {
+        collect_node_meshes(&child, &transform, buffers, image_names, out);
+    }
+}
+
+/// Loads the meshes of the default scene of a .gltf or .glb file, with the
+/// node transforms applied.
+pub fn try_load_gltf(path: &OsStr) -> Result<LoadedGltf, CleanerError> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path).map_err(gltf_error(path))?;
+    let buffers = gltf::import_buffers(&document, Path::new(path).parent(), blob)
+        .map_err(gltf_error(path))?;
+
+    let mut embedded_textures = HashMap::new();
+    let image_names = collect_images(Path::new(path), &document, &buffers, &mut embedded_textures)?;
+
+    let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        return Ok((vec![], embedded_textures));
+    };
+
+    let mut out = vec![];
+    for node in scene.nodes() {
+        collect_node_meshes(&node, &Mat4::identity(), &buffers, &image_names, &mut out);
+    }
+
+    Ok((out, embedded_textures))
+}
+
+/// Appends data to the binary chunk as a new 4-byte aligned buffer view
+fn push_buffer_view(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<Value>,
+    data: &[u8],
+    target: Option<u32>,
+) -> usize {
+    while !bin.len().is_multiple_of(4) {
+        bin.push(0);
+    }
+
+    let mut view = json!({
+        "buffer": 0,
+        "byteOffset": bin.len(),
+        "byteLength": data.len(),
+    });
+    if let Some(target) = target {
+        view["target"] = json!(target);
+    }
+
+    bin.extend_from_slice(data);
+    views.push(view);
+    views.len() - 1
+}
+
+fn f32_bytes(values: impl Iterator<Item = f32>) -> Vec<u8> {
+    values.flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Writes the model as a binary glTF to dest. `images` maps the texture names
+/// used in the materials to the images to write for them.
+pub fn write_glb(
+    dest: &Path,
+    model: &Model,
+    images: &HashMap<String, GlbImage>,
+) -> Result<(), CleanerError> {
+    let mut bin = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+    let mut materials = Vec::new();
+    let mut textures = Vec::new();
+    let mut json_images = Vec::new();
+    let mut texture_indices: HashMap<String, usize> = HashMap::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+
+    let mut texture_index = |name: &str, bin: &mut Vec<u8>, views: &mut Vec<Value>| {
+        let image = images.get(name)?;
+        if let Some(index) = texture_indices.get(name) {
+            return Some(*index);
+        }
+
+        let json_image = match image {
+            GlbImage::External(uri) => json!({ "uri": uri }),
+            GlbImage::Embedded { data, mime_type } => {
+                let view = push_buffer_view(bin, views, data, None);
+                json!({ "bufferView": view, "mimeType": mime_type })
+            }
+        };
+        json_images.push(json_image);
+        textures.push(json!({ "source": json_images.len() - 1 }));
+        texture_indices.insert(name.to_string(), textures.len() - 1);
+        Some(textures.len() - 1)
+    };
+
+    for mesh in model.meshes.iter() {
+        let positions = positions_f32(&mesh.mesh)?;
+        let indices = indices_u32(&mesh.mesh)?;
+        // A mesh without triangles would give empty accessors, leave it out
+        if indices.is_empty() {
+            continue;
+        }
+        let mut attributes = serde_json::Map::new();
+
+        let aabb = mesh.mesh.compute_aabb();
+        let view = push_buffer_view(
+            &mut bin,
+            &mut views,
+            &f32_bytes(positions.iter().flat_map(|p| [p.x, p.y, p.z])),
+            Some(GL_ARRAY_BUFFER),
+        );
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": GL_FLOAT,
+            "count": positions.len(),
+            "type": "VEC3",
+            "min": [aabb.min().x, aabb.min().y, aabb.min().z],
+            "max": [aabb.max().x, aabb.max().y, aabb.max().z],
+        }));
+        attributes.insert("POSITION".to_string(), json!(accessors.len() - 1));
+
+        if let Some(normals) = &mesh.mesh.normals {
+            let view = push_buffer_view(
+                &mut bin,
+                &mut views,
+                &f32_bytes(normals.iter().flat_map(|n| [n.x, n.y, n.z])),
+                Some(GL_ARRAY_BUFFER),
+            );
+            accessors.push(json!({
+                "bufferView": view,
+                "componentType": GL_FLOAT,
+                "count": normals.len(),
+                "type": "VEC3",
+            }));
+            attributes.insert("NORMAL".to_string(), json!(accessors.len() - 1));
+        }
+
+        if let Some(uvs) = &mesh.mesh.uvs {
+            let view = push_buffer_view(
+                &mut bin,
+                &mut views,
+                &f32_bytes(uvs.iter().flat_map(|uv| [uv.x, 1.0 - uv.y])),
+                Some(GL_ARRAY_BUFFER),
+            );
+            accessors.push(json!({
+                "bufferView": view,
+                "componentType": GL_FLOAT,
+                "count": uvs.len(),
+                "type": "VEC2",
+            }));
+            attributes.insert("TEXCOORD_0".to_string(), json!(accessors.len() - 1));
+        }
+
+        let view = push_buffer_view(
+            &mut bin,
+            &mut views,
+            &indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect::<Vec<_>>(),
+            Some(GL_ELEMENT_ARRAY_BUFFER),
+        );
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": GL_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        let indices_accessor = accessors.len() - 1;
+
+        let material = &mesh.material;
+        // Meshes can share materials, write each of them once
+        let material_index = match material_indices.get(&material.name) {
+            Some(index) => *index,
+            None => {
+                let diffuse = material.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+                let param = |key: &str, default: f32| {
+                    material
+                        .unknown_param
+                        .get(key)
+                        .and_then(|v| v.parse::<f32>().ok())
+                        .unwrap_or(default)
+                };
+
+                let mut pbr = json!({
+                    "baseColorFactor": [diffuse[0], diffuse[1], diffuse[2], material.dissolve.unwrap_or(1.0)],
+                    "metallicFactor": param("Pm", 0.0),
+                    "roughnessFactor": param("Pr", 1.0),
+                });
+                let mut json_material = json!({ "name": material.name });
+
+                if let Some(texture) = material
+                    .diffuse_texture
+                    .as_deref()
+                    .and_then(|name| texture_index(name, &mut bin, &mut views))
+                {
+                    pbr["baseColorTexture"] = json!({ "index": texture });
+                }
+                if let Some(texture) = material
+                    .normal_texture
+                    .as_deref()
+                    .and_then(|name| texture_index(name, &mut bin, &mut views))
+                {
+                    json_material["normalTexture"] = json!({ "index": texture });
+                }
+                json_material["pbrMetallicRoughness"] = pbr;
+                materials.push(json_material);
+                material_indices.insert(material.name.clone(), materials.len() - 1);
+                materials.len() - 1
+            }
+        };
+
+        let node_name = mesh
+            .names
+            .object
+            .as_ref()
+            .or(mesh.names.group.as_ref())
+            .unwrap_or(&material.name);
+        meshes.push(json!({
+            "name": mesh.names.group.as_ref().unwrap_or(node_name),
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices_accessor,
+                "material": material_index,
+            }],
+        }));
+        nodes.push(json!({ "name": node_name, "mesh": meshes.len() - 1 }));
+    }
+
+    while !bin.len().is_multiple_of(4) {
+        bin.push(0);
+    }
+
+    let mut root = json!({
+        "asset": { "version": "2.0", "generator": "obj-overlap-cleaner" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+    if !textures.is_empty() {
+        root["textures"] = json!(textures);
+        root["images"] = json!(json_images);
+    }
+
+    let mut json_chunk = serde_json::to_vec(&root).expect("glTF JSON is serializable");
+    while !json_chunk.len().is_multiple_of(4) {
+        json_chunk.push(b' ');
+    }
+
+    let total_len = 12 + 8 + json_chunk.len() + 8 + bin.len();
+    let write_chunks = || -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(dest)?);
+        writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+        writer.write_all(&GLB_VERSION.to_le_bytes())?;
+        writer.write_all(&(total_len as u32).to_le_bytes())?;
+        writer.write_all(&(json_chunk.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+        writer.write_all(&json_chunk)?;
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+        writer.write_all(&bin)?;
+        writer.flush()
+    };
+
+    write_chunks().map_err(|e| CleanerError::io(dest, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::MeshContainer, test_utils::TempDir};
+    use three_d_asset::{Indices, Positions, Vec2, Vec3};
+
+    #[test]
+    fn test_glb_round_trip() {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: Some(vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(1.0, 0.25),
+            ]),
+            colors: None,
+        };
+        let material = TobjMaterial {
+            name: "test".to_string(),
+            diffuse: Some([0.5, 0.25, 1.0]),
+            ..Default::default()
+        };
+        // Everything of this mesh was cut away, it must not end up in the file
+        let empty = TriMesh {
+            positions: Positions::F32(vec![]),
+            indices: Indices::U32(vec![]),
+            uvs: Some(vec![]),
+            ..trimesh.clone()
+        };
+        let empty = MeshContainer::new(empty, material.clone(), false, None).unwrap();
+        let mut mesh = MeshContainer::new(trimesh, material, false, None).unwrap();
+        mesh.names = MeshNames {
+            object: Some("tile".to_string()),
+            group: Some("roof".to_string()),
+        };
+        let aabb = mesh.mesh.compute_aabb();
+        let model = Model {
+            meshes: vec![empty, mesh],
+            aabb,
+            source_file: "test.obj".into(),
+            texture_downscale_factor: 1,
+            embedded_textures: HashMap::new(),
+            overlapping_assets: Default::default(),
+            threshold_range: None,
+            load_time: Default::default(),
+            out_path: "test.obj".into(),
+            texture_plan: Default::default(),
+        };
+
+        let dir = TempDir::new("gltf_round_trip");
+        let dest = dir.join("test.glb");
+        write_glb(&dest, &model, &HashMap::new()).unwrap();
+        let (meshes, _) = try_load_gltf(dest.as_os_str()).unwrap();
+
+        assert_eq!(meshes.len(), 1);
+        let (mesh, material, names) = &meshes[0];
+        assert_eq!(
+            positions_f32(mesh).unwrap(),
+            positions_f32(&model.meshes[1].mesh).unwrap()
+        );
+        assert_eq!(indices_u32(mesh).unwrap(), [0, 1, 2]);
+        assert_eq!(mesh.uvs, model.meshes[1].mesh.uvs);
+        assert_eq!(material.name, "test");
+        assert_eq!(material.diffuse, Some([0.5, 0.25, 1.0]));
+        assert_eq!(*names, model.meshes[1].names);
+    }
+
+    #[test]
+    fn test_data_uri_images_embedded() {
+        let positions = f32_bytes([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0].into_iter());
+        let gltf = |image_uri: &str| {
+            json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{
+                    "byteLength": positions.len(),
+                    "uri": format!("data:application/octet-stream;base64,{}", base64::encode(&positions)),
+                }],
+                "bufferViews": [{ "buffer": 0, "byteLength": positions.len() }],
+                "accessors": [{
+                    "bufferView": 0,
+                    "componentType": GL_FLOAT,
+                    "count": 3,
+                    "type": "VEC3",
+                    "min": [0.0, 0.0, 0.0],
+                    "max": [1.0, 1.0, 0.0],
+                }],
+                "images": [{ "uri": image_uri }],
+                "textures": [{ "source": 0 }],
+                "materials": [{
+                    "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } },
+                }],
+                "meshes": [{
+                    "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }],
+                }],
+                "nodes": [{ "mesh": 0 }],
+                "scenes": [{ "nodes": [0] }],
+            })
+            .to_string()
+        };
+        let dir = TempDir::new("gltf_data_uri");
+        let path = dir.join("tile.gltf");
+        let image = b"\x89PNG\r\n\x1a\nimage".to_vec();
+
+        let uri = format!("data:image/jpeg;base64,{}", base64::encode(&image));
+        std::fs::write(&path, gltf(&uri)).unwrap();
+        let (meshes, embedded_textures) = try_load_gltf(path.as_os_str()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(
+            meshes[0].1.diffuse_texture.as_deref(),
+            Some("tile_image_0.jpg")
+        );
+        assert_eq!(embedded_textures["tile_image_0.jpg"], image);
+
+        std::fs::write(&path, gltf("data:image/png;base64,not base64!")).unwrap();
+        assert!(matches!(
+            try_load_gltf(path.as_os_str()),
+            Err(CleanerError::Gltf {
+                source: gltf::Error::Base64(_),
+                ..
+            })
+        ));
+    }
+}
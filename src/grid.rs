@@ -1,20 +1,152 @@
-use std::collections::HashMap;
-use three_d_asset::{Indices, Positions, TriMesh, Vec3, Vector3};
+use std::collections::{HashMap, HashSet};
+use three_d_asset::{InnerSpace, TriMesh, Vec3, Vector3};
 
-const GRID_RESOLUTION: u32 = 10;
+use crate::{
+    error::CleanerError,
+    model::{indices_u32, mean_edge_length, positions_f32},
+};
+
+/// How the cell size of an [`IndexGrid`] is chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GridCellMode {
+    /// The value is multiplied with the mean edge length of the mesh
+    #[default]
+    MeanEdge,
+    /// The value is the number of cells along the longest side of the mesh
+    /// bounding box
+    Aabb,
+    /// The value is the cell size in model units
+    Explicit,
+}
+
+impl GridCellMode {
+    /// Value used when none is given
+    pub fn default_value(&self) -> f32 {
+        match self {
+            Self::MeanEdge => 4.0,
+            Self::Aabb => 100.0,
+            Self::Explicit => 0.1,
+        }
+    }
+}
+
+/// Cell size of an [`IndexGrid`], relative to the mesh it indexes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridCellSize {
+    pub mode: GridCellMode,
+    pub value: f32,
+}
+
+impl Default for GridCellSize {
+    fn default() -> Self {
+        Self {
+            mode: GridCellMode::MeanEdge,
+            value: GridCellMode::MeanEdge.default_value(),
+        }
+    }
+}
+
+impl GridCellSize {
+    /// Resolves the cell size for mesh, which needs triangles with
+    /// [`GridCellMode::MeanEdge`]. Degenerate meshes, e.g. with all vertices
+    /// at one point, get unit cells.
+    pub fn resolve(&self, mesh: &TriMesh) -> Result<f32, CleanerError> {
+        let size = match self.mode {
+            GridCellMode::MeanEdge => self.value * mean_edge_length(mesh)?,
+            GridCellMode::Aabb => {
+                let aabb = mesh.compute_aabb();
+                let size = aabb.size();
+                size.x.max(size.y).max(size.z) / self.value
+            }
+            GridCellMode::Explicit => self.value,
+        };
+
+        if size.is_finite() && size > 0.0 {
+            Ok(size)
+        } else {
+            Ok(1.0)
+        }
+    }
+}
+
+/// Separating axis test between a triangle and an axis aligned box given by
+/// its center and half size. Touching counts as overlapping.
+fn triangle_overlaps_box(tri: [Vec3; 3], center: Vec3, half_size: Vec3) -> bool {
+    let v = tri.map(|p| p - center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+    let box_axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+
+    let separated = |axis: Vec3| {
+        let p = v.map(|p| axis.dot(p));
+        let r =
+            half_size.x * axis.x.abs() + half_size.y * axis.y.abs() + half_size.z * axis.z.abs();
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+
+    // Box face normals, the triangle normal and the edge cross products
+    box_axes.iter().all(|axis| !separated(*axis))
+        && !separated(edges[0].cross(edges[1]))
+        && box_axes
+            .iter()
+            .all(|axis| edges.iter().all(|edge| !separated(axis.cross(*edge))))
+}
+
+/// Cell count and occupancy of an [`IndexGrid`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GridStats {
+    pub cell_size: f32,
+    /// Number of cells with at least one triangle
+    pub occupied_cells: usize,
+    /// Number of cells in the bounding box of the occupied cells
+    pub bounding_cells: usize,
+    /// Number of triangle entries over all cells
+    pub triangle_refs: usize,
+    /// Largest number of triangles in a single cell
+    pub max_cell_triangles: usize,
+}
+
+impl GridStats {
+    /// Share of the bounding cells that are occupied
+    pub fn occupancy(&self) -> f32 {
+        match self.bounding_cells {
+            0 => 0.0,
+            n => self.occupied_cells as f32 / n as f32,
+        }
+    }
+
+    pub fn mean_cell_triangles(&self) -> f32 {
+        match self.occupied_cells {
+            0 => 0.0,
+            n => self.triangle_refs as f32 / n as f32,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct IndexGrid {
+    cell_size: f32,
     indices: HashMap<i32, HashMap<i32, HashMap<i32, Vec<u32>>>>,
 }
 
 impl IndexGrid {
-    pub fn new() -> Self {
+    /// # Panics
+    /// If cell_size isn't positive
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Grid cell size must be positive");
         Self {
+            cell_size,
             indices: HashMap::new(),
         }
     }
 
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell_of(&self, p: Vec3) -> Vector3<i32> {
+        p.map(|x| (x / self.cell_size).floor() as i32)
+    }
+
     fn get_cell(&self, x: i32, y: i32, z: i32) -> Option<&[u32]> {
         let yz = self.indices.get(&x)?;
         let z_indices = yz.get(&y)?;
@@ -23,19 +155,24 @@ impl IndexGrid {
         Some(indices)
     }
 
+    /// Returns the vertex indices of the triangles in the cells within
+    /// threshold from p. Each triangle is returned once.
     pub fn get_indices(&self, p: &Vec3, threshold: f32) -> Vec<u32> {
-        let p_min = (p - Vec3::new(threshold, threshold, threshold))
-            .map(|x| (x * GRID_RESOLUTION as f32) as i32);
-        let p_max = (p + Vec3::new(threshold, threshold, threshold))
-            .map(|x| (x * GRID_RESOLUTION as f32) as i32);
+        let p_min = self.cell_of(p - Vec3::new(threshold, threshold, threshold));
+        let p_max = self.cell_of(p + Vec3::new(threshold, threshold, threshold));
 
         let mut indices = Vec::new();
+        let mut seen = HashSet::new();
 
         for x in p_min.x..(p_max.x + 1) {
             for y in p_min.y..(p_max.y + 1) {
                 for z in p_min.z..(p_max.z + 1) {
                     if let Some(ind) = self.get_cell(x, y, z) {
-                        indices.extend_from_slice(ind);
+                        for tri in ind.chunks_exact(3) {
+                            if seen.insert(tri) {
+                                indices.extend_from_slice(tri);
+                            }
+                        }
                     }
                 }
             }
@@ -52,34 +189,198 @@ impl IndexGrid {
         indices.extend_from_slice(index_slice);
     }
 
-    pub fn populate_from_trimesh(&mut self, mesh: &TriMesh) {
-        let positions = match &mesh.positions {
-            Positions::F32(postions) => postions,
-            _ => panic!("Positions not F32"),
-        };
+    pub fn populate_from_trimesh(&mut self, mesh: &TriMesh) -> Result<(), CleanerError> {
+        let positions = positions_f32(mesh)?;
+        let indices = indices_u32(mesh)?;
 
-        let indices = match &mesh.indices {
-            Indices::U32(indices) => indices,
-            _ => panic!("Indices not U32"),
-        };
+        let half_size = Vec3::new(0.5, 0.5, 0.5) * self.cell_size;
 
+        // Insert each triangle to all cells it overlaps with
         for tri in indices.chunks_exact(3) {
-            // TODO: Push to neighboring cells, if some of p1 or p2 falls on neighbor side
-            let p0: Vector3<i32> =
-                positions[tri[0] as usize].map(|x| (x * GRID_RESOLUTION as f32) as i32);
-            let p1: Vector3<i32> =
-                positions[tri[1] as usize].map(|x| (x * GRID_RESOLUTION as f32) as i32);
-            let p2: Vector3<i32> =
-                positions[tri[2] as usize].map(|x| (x * GRID_RESOLUTION as f32) as i32);
-            self.extend(p0, tri);
-
-            if p1 != p0 {
-                self.extend(p1, tri);
+            let points = [
+                positions[tri[0] as usize],
+                positions[tri[1] as usize],
+                positions[tri[2] as usize],
+            ];
+            let c0 = self.cell_of(points[0]);
+            let c1 = self.cell_of(points[1]);
+            let c2 = self.cell_of(points[2]);
+
+            // Small triangles are fully within one cell
+            if c0 == c1 && c1 == c2 {
+                self.extend(c0, tri);
+                continue;
+            }
+
+            let c_min = Vector3::new(
+                c0.x.min(c1.x).min(c2.x),
+                c0.y.min(c1.y).min(c2.y),
+                c0.z.min(c1.z).min(c2.z),
+            );
+            let c_max = Vector3::new(
+                c0.x.max(c1.x).max(c2.x),
+                c0.y.max(c1.y).max(c2.y),
+                c0.z.max(c1.z).max(c2.z),
+            );
+
+            for x in c_min.x..(c_max.x + 1) {
+                for y in c_min.y..(c_max.y + 1) {
+                    for z in c_min.z..(c_max.z + 1) {
+                        let center = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5)
+                            * self.cell_size;
+                        if triangle_overlaps_box(points, center, half_size) {
+                            self.extend(Vector3::new(x, y, z), tri);
+                        }
+                    }
+                }
             }
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> GridStats {
+        let mut stats = GridStats {
+            cell_size: self.cell_size,
+            ..Default::default()
+        };
+        let mut min = Vector3::new(i32::MAX, i32::MAX, i32::MAX);
+        let mut max = Vector3::new(i32::MIN, i32::MIN, i32::MIN);
+
+        for (x, yz) in self.indices.iter() {
+            for (y, z_indices) in yz.iter() {
+                for (z, indices) in z_indices.iter() {
+                    let triangles = indices.len() / 3;
+                    stats.occupied_cells += 1;
+                    stats.triangle_refs += triangles;
+                    stats.max_cell_triangles = stats.max_cell_triangles.max(triangles);
 
-            if p2 != p1 && p2 != p0 {
-                self.extend(p2, tri);
+                    min = Vector3::new(min.x.min(*x), min.y.min(*y), min.z.min(*z));
+                    max = Vector3::new(max.x.max(*x), max.y.max(*y), max.z.max(*z));
+                }
             }
         }
+
+        if stats.occupied_cells > 0 {
+            stats.bounding_cells = (0..3).map(|i| (max[i] - min[i]) as usize + 1).product();
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use three_d_asset::{Indices, Positions};
+
+    fn strip(scale: f32) -> TriMesh {
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for i in 0..10 {
+            positions.push(Vec3::new(i as f32 * scale, 0.0, 0.0));
+            positions.push(Vec3::new(i as f32 * scale, scale, 0.0));
+            if i > 0 {
+                let i0 = 2 * i - 2;
+                indices.extend_from_slice(&[i0, i0 + 2, i0 + 1, i0 + 1, i0 + 2, i0 + 3]);
+            }
+        }
+        TriMesh {
+            positions: Positions::F32(positions),
+            indices: Indices::U32(indices),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        }
+    }
+
+    #[test]
+    fn test_cell_size_follows_mesh_scale() {
+        let cell_size = GridCellSize {
+            mode: GridCellMode::MeanEdge,
+            value: 1.0,
+        };
+        let small = cell_size.resolve(&strip(0.001)).unwrap();
+        let large = cell_size.resolve(&strip(1000.0)).unwrap();
+        assert!((large / small - 1e6).abs() < 1.0);
+
+        let mut small_grid = IndexGrid::new(small);
+        small_grid.populate_from_trimesh(&strip(0.001)).unwrap();
+        let mut large_grid = IndexGrid::new(large);
+        large_grid.populate_from_trimesh(&strip(1000.0)).unwrap();
+        assert_eq!(
+            small_grid.stats().occupied_cells,
+            large_grid.stats().occupied_cells
+        );
+
+        let aabb_size = GridCellSize {
+            mode: GridCellMode::Aabb,
+            value: 9.0,
+        };
+        assert!((aabb_size.resolve(&strip(2.0)).unwrap() - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_large_triangle_found_away_from_vertices() {
+        let mesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 10.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+        let mut grid = IndexGrid::new(1.0);
+        grid.populate_from_trimesh(&mesh).unwrap();
+
+        assert_eq!(grid.get_indices(&Vec3::new(3.5, 3.5, 0.2), 0.1), [0, 1, 2]);
+        // Next to the hypotenuse, but outside of the triangle
+        assert!(grid.get_indices(&Vec3::new(8.5, 8.5, 0.0), 0.1).is_empty());
+        // Cells on and below the diagonal, not the ones above it
+        assert!(grid.stats().occupied_cells < 100);
+    }
+
+    #[test]
+    fn test_triangle_overlaps_box() {
+        let tri = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+        ];
+        let half_size = Vec3::new(0.5, 0.5, 0.5);
+
+        assert!(triangle_overlaps_box(
+            tri,
+            Vec3::new(1.5, 1.5, 0.5),
+            half_size
+        ));
+        assert!(!triangle_overlaps_box(
+            tri,
+            Vec3::new(3.5, 3.5, 0.5),
+            half_size
+        ));
+        assert!(!triangle_overlaps_box(
+            tri,
+            Vec3::new(1.5, 1.5, 1.5),
+            half_size
+        ));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut grid = IndexGrid::new(1.0);
+        grid.populate_from_trimesh(&strip(1.0)).unwrap();
+        let stats = grid.stats();
+
+        assert_eq!(stats.occupied_cells, 20);
+        assert_eq!(stats.bounding_cells, 20);
+        assert_eq!(stats.occupancy(), 1.0);
+        assert!(stats.max_cell_triangles >= 1);
+        assert!(stats.triangle_refs >= 18);
     }
 }
@@ -1,17 +1,26 @@
 use std::{
-    ffi::OsString,
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
     fs::File,
-    io::{BufWriter, Write},
-    path::{Path, PathBuf},
+    io::{BufRead, BufReader, BufWriter, Cursor, Write},
+    path::{Component, Path, PathBuf},
     sync::{Arc, Mutex, mpsc},
 };
 
-use image::ImageReader;
+use glob::{MatchOptions, Pattern};
+use image::{
+    DynamicImage, ImageFormat, ImageReader,
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+};
 use three_d_asset::{Vec2, Vec3};
 
+use crate::error::CleanerError;
+use crate::gltf_io::{self, GlbImage};
 use crate::messages;
 use crate::messages::ModelLoadTask;
-use crate::model::{Model, ModelReference, OutAsset};
+use crate::model::{
+    AssetFormat, CutMode, MeshContainer, Model, ModelReference, OutAsset, VertexClass, indices_u32,
+};
 
 pub fn model_load_runner(
     rx: Arc<Mutex<mpsc::Receiver<ModelLoadTask>>>,
@@ -28,15 +37,28 @@ pub fn model_load_runner(
             Ok(task) => match task {
                 ModelLoadTask::Task(task) => {
                     let path = task.path;
-                    let model = Model::try_new_from_file(path.clone(), true, false, 2)
-                        .unwrap_or_else(|_| panic!("Failed loading model from {path:?}"));
+                    let response = match Model::try_new_from_file(
+                        path.clone(),
+                        true,
+                        task.index_grid_cell_size,
+                        task.texture_downscale_factor,
+                    ) {
+                        Ok(model) => {
+                            println!("Successfully loaded model from: {path:?}");
+                            messages::ModelLoadTaskResponse::Model(messages::ModelContainer {
+                                model: Box::new(model),
+                            })
+                        }
+                        Err(error) => {
+                            println!("Failed loading model from {path:?}: {error}");
+                            messages::ModelLoadTaskResponse::Failed(messages::FailedContainer {
+                                path,
+                                error,
+                            })
+                        }
+                    };
 
-                    println!("Successfully loaded model from: {path:?}");
-
-                    tx.send(messages::ModelLoadTaskResponse::Model(
-                        messages::ModelContainer { model },
-                    ))
-                    .expect("Failed to send result");
+                    tx.send(response).expect("Failed to send result");
                 }
                 ModelLoadTask::Terminate => {
                     tx.send(messages::ModelLoadTaskResponse::Terminated)
@@ -49,276 +71,2388 @@ pub fn model_load_runner(
     }
 }
 
-pub fn scan_folder_for_objs(folder: &OsString) -> impl Iterator<Item = OsString> {
-    let folder = std::path::Path::new(&folder);
+/// Output file format of the processed assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Wavefront OBJ with an MTL library and texture files
+    #[default]
+    Obj,
+    /// Binary glTF
+    Glb,
+}
+
+impl OutputFormat {
+    /// File extension of the written assets
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Obj => "obj",
+            Self::Glb => "glb",
+        }
+    }
+}
+
+/// How assets that would be written to the same output path are told apart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CollisionMode {
+    /// Number the later of the colliding files, e.g. `tile_1.obj`
+    #[default]
+    Rename,
+    /// Write the assets of each input folder to a subfolder named after the
+    /// input folder, numbering the files that still collide
+    Subfolders,
+}
 
-    let mut read_dir = folder.read_dir().unwrap();
+/// Output names of the texture files of an asset, planned for all assets
+/// before writing so that assets sharing an output folder don't overwrite
+/// each other's textures
+#[derive(Debug, Default, Clone)]
+pub struct TexturePlan {
+    /// Output file of each renamed texture file, relative to the asset
+    pub renames: HashMap<String, String>,
+    /// Texture files that another asset writes to the same place
+    pub shared: HashSet<String>,
+    /// Region each cropped texture file is cropped to
+    pub crops: HashMap<String, TextureCrop>,
+}
 
-    std::iter::from_fn(move || {
-        loop {
-            let entry = read_dir.next()?;
-            let p = entry.unwrap().path();
+impl TexturePlan {
+    /// Output file of texture_file written as format, relative to the asset
+    pub fn out_name(&self, texture_file: &str, format: TextureFormat) -> String {
+        let name = self
+            .renames
+            .get(texture_file)
+            .map(|name| name.as_str())
+            .unwrap_or(texture_file);
+        format.file_name(name)
+    }
+}
 
-            if let Some(extension) = p.extension()
-                && extension.eq_ignore_ascii_case("obj")
-            {
-                return Some(p.into_os_string());
-            }
+/// Regions of a texture that are kept when cropping it, packed to a smaller
+/// texture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureCrop {
+    /// Size of the cropped texture
+    pub width: u32,
+    pub height: u32,
+    pub charts: Vec<CropChart>,
+}
+
+/// Pixel region of a texture that is kept when cropping it and its position
+/// in the cropped texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropChart {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub dest_x: u32,
+    pub dest_y: u32,
+}
+
+/// Pixels kept around each used uv chart when cropping textures, so that
+/// texture filtering doesn't sample outside of it
+const CROP_MARGIN: u32 = 2;
+
+/// Cells along each side of the occupancy grid the used uvs are grouped to
+/// charts on. Triangles in touching cells end up in the same chart.
+const CHART_GRID_CELLS: usize = 64;
+
+/// Share of the texture area below which a crop is reported as saving
+/// little, when the used uvs cover most of the texture
+const SMALL_CROP_SAVING: f32 = 0.25;
+
+/// File format of the written textures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextureFormat {
+    /// Keep the format of the source texture
+    #[default]
+    Keep,
+    Png,
+    /// JPEG with the quality of [`TextureEncoding::jpeg_quality`]
+    Jpeg,
+    /// Lossless WebP. Textures embedded in GLB files are stored as PNG
+    /// instead, as glTF only supports PNG and JPEG.
+    Webp,
+}
+
+impl TextureFormat {
+    /// Extension of the written texture files, None to keep the source one
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Keep => None,
+            Self::Png => Some("png"),
+            Self::Jpeg => Some("jpg"),
+            Self::Webp => Some("webp"),
         }
-    })
+    }
+
+    /// texture_file with the extension of the format
+    pub fn file_name(&self, texture_file: &str) -> String {
+        match self.extension() {
+            Some(extension) => Path::new(texture_file)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            None => texture_file.to_string(),
+        }
+    }
 }
 
-pub fn scan_folder_and_create_tasks(
-    folder: &OsString,
-    tx: &mpsc::Sender<crate::messages::ModelLoadTask>,
-) {
-    for obj_file in scan_folder_for_objs(folder) {
-        tx.send(crate::messages::ModelLoadTask::Task(
-            crate::messages::TaskContainer { path: obj_file },
-        ))
-        .expect("Error while sending task");
+/// How the written textures are encoded
+#[derive(Debug, Clone, Copy)]
+pub struct TextureEncoding {
+    pub format: TextureFormat,
+    /// Quality of JPEG textures, 1-100
+    pub jpeg_quality: u8,
+}
+
+impl Default for TextureEncoding {
+    fn default() -> Self {
+        Self {
+            format: TextureFormat::Keep,
+            jpeg_quality: 90,
+        }
     }
 }
 
-fn copy_texture(
-    texture_file: &str,
-    source_folder: &Path,
-    dest_folder: &Path,
-    downscale_factor: u32,
-) {
-    let texture_src = source_folder.join(texture_file);
-    let texture_dst = dest_folder.join(texture_file);
-    if texture_dst.exists() {
-        return;
+/// Options for writing the assets to the output folder
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    pub format: OutputFormat,
+    /// Store the textures in the GLB files instead of next to them
+    pub embed_textures: bool,
+    pub texture_encoding: TextureEncoding,
+}
+
+/// Include and exclude globs for the assets found by
+/// [`scan_folder_for_assets`]. Globs without a `/` are matched against file
+/// and folder names, others against the path relative to the scanned folder.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AssetFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl AssetFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, CleanerError> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).map_err(|source| CleanerError::InvalidPattern {
+                        pattern: pattern.clone(),
+                        source,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
     }
 
-    if !texture_src.exists() {
-        panic!("Unable to load texture: {texture_src:?}");
+    fn matches(pattern: &Pattern, relative_path: &Path) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        match pattern.as_str().contains('/') {
+            true => pattern.matches_path_with(relative_path, options),
+            false => relative_path
+                .file_name()
+                .is_some_and(|name| pattern.matches_path_with(Path::new(name), options)),
+        }
     }
 
-    if downscale_factor == 1 {
-        std::fs::copy(texture_src, texture_dst).expect("Failed to copy texture");
-    } else {
-        let img = ImageReader::open(texture_src)
-            .expect("Couldnt open image")
-            .decode()
-            .expect("Couldnt decode image");
-
-        let resized = img.resize_exact(
-            img.width() / downscale_factor,
-            img.height() / downscale_factor,
-            image::imageops::FilterType::Triangle,
-        );
+    /// Whether a file or folder is skipped
+    fn excludes(&self, relative_path: &Path) -> bool {
+        self.exclude.iter().any(|p| Self::matches(p, relative_path))
+    }
+
+    /// Whether an asset file is used
+    fn accepts(&self, relative_path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| Self::matches(p, relative_path)))
+            && !self.excludes(relative_path)
+    }
+}
+
+/// Lists the files in folder and its subfolders that can be loaded as assets
+/// and pass filter, sorted
+pub fn scan_folder_for_assets(
+    folder: &OsString,
+    filter: &AssetFilter,
+) -> Result<Vec<OsString>, CleanerError> {
+    let root = Path::new(folder);
+
+    let mut assets = vec![];
+    let mut folders = vec![root.to_path_buf()];
+
+    while let Some(folder) = folders.pop() {
+        let read_dir = folder
+            .read_dir()
+            .map_err(|e| CleanerError::io(&folder, e))?;
+
+        for entry in read_dir {
+            let entry = entry.map_err(|e| CleanerError::io(&folder, e))?;
+            let p = entry.path();
+            let relative_path = p.strip_prefix(root).unwrap_or(&p);
+
+            // Symlinked folders aren't followed to avoid cycles
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if is_dir {
+                if !filter.excludes(relative_path) {
+                    folders.push(p);
+                }
+                continue;
+            }
 
-        resized.save(texture_dst).expect("Couldnt save image");
+            if AssetFormat::from_path(p.as_os_str()).is_some() && filter.accepts(relative_path) {
+                assets.push(p.into_os_string());
+            }
+        }
     }
+
+    assets.sort();
+    Ok(assets)
 }
 
-fn write_header(writer: &mut BufWriter<File>) {
-    writeln!(writer, "#").expect("Failed to write mesh");
-    writeln!(writer, "# Wavefront OBJ file").expect("Failed to write mesh");
-    writeln!(writer, "# Created by obj-overlap-cleaner").expect("Failed to write mesh");
-    writeln!(writer, "# https://github.com/sanox-oy/obj-overlap-cleaner")
-        .expect("Failed to write mesh");
-    writeln!(writer, "#").expect("Failed to write mesh");
+/// Splits a source file path into its parent folder and file name
+fn split_source_path(source_file: &OsString) -> Result<(&Path, &OsStr), CleanerError> {
+    let source = Path::new(source_file);
+    let invalid_path = || CleanerError::InvalidPath(source_file.clone());
+
+    let source_folder = source.parent().ok_or_else(invalid_path)?;
+    let filename = source.file_name().ok_or_else(invalid_path)?;
+
+    Ok((source_folder, filename))
 }
 
-fn write_mtllib(
-    source_folder: &Path,
-    dest_folder: &Path,
-    dest: PathBuf,
-    materials: &[&tobj::Material],
-    texture_downscale_factor: u32,
-) {
-    let file = File::create(dest).expect("Couldnt create file");
-    let mut file_buf = BufWriter::new(file);
+/// Splits an OBJ or MTL line to its statement and the rest of the line,
+/// e.g. `mtllib tile materials.mtl` to `mtllib` and `tile materials.mtl`
+fn split_statement(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((statement, rest)) => (statement, rest.trim()),
+        None => (line, ""),
+    }
+}
+
+/// Line ending of line, so that rewritten lines keep it
+fn line_ending(line: &[u8]) -> &'static str {
+    match line {
+        [.., b'\r', b'\n'] => "\r\n",
+        [.., b'\n'] => "\n",
+        _ => "",
+    }
+}
 
-    write_header(&mut file_buf);
+/// Copies the OBJ file source to dest line by line. With `merged_mtl`, the
+/// first `mtllib` statement is replaced by one naming it and the others are
+/// left out. Returns the MTL files the source names, relative to it.
+fn copy_obj_lines(
+    source: &Path,
+    dest: &Path,
+    merged_mtl: Option<&str>,
+) -> Result<Vec<String>, CleanerError> {
+    let file = File::open(source).map_err(|e| CleanerError::io(source, e))?;
+    let mut reader = BufReader::new(file);
+    let dest_file = File::create(dest).map_err(|e| CleanerError::io(dest, e))?;
+    let mut writer = BufWriter::new(dest_file);
 
-    for material in materials {
-        writeln!(file_buf).expect("Failed to write mesh");
-        writeln!(file_buf, "newmtl {}", material.name).expect("Failed to write mesh");
-        if let Some(ka) = material.ambient {
-            writeln!(file_buf, "Ka {} {} {}", ka[0], ka[1], ka[2]).expect("Failed to write mesh");
+    let mut mtllibs = vec![];
+    let mut line = vec![];
+    loop {
+        line.clear();
+        let len = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|e| CleanerError::io(source, e))?;
+        if len == 0 {
+            break;
         }
-        if let Some(kd) = material.diffuse {
-            writeln!(file_buf, "Kd {} {} {}", kd[0], kd[1], kd[2]).expect("Failed to write mesh");
+
+        let text = String::from_utf8_lossy(&line);
+        let (statement, mtllib) = split_statement(&text);
+        let written = match statement {
+            // Like tobj, the rest of the statement is a single file name
+            "mtllib" if !mtllib.is_empty() => {
+                mtllibs.push(mtllib.to_string());
+                match merged_mtl {
+                    Some(_) if mtllibs.len() > 1 => None,
+                    Some(merged_mtl) => {
+                        Some(format!("mtllib {merged_mtl}{}", line_ending(&line)).into_bytes())
+                    }
+                    None => Some(line.clone()),
+                }
+            }
+            _ => Some(line.clone()),
+        };
+        if let Some(written) = written {
+            writer
+                .write_all(&written)
+                .map_err(|e| CleanerError::io(dest, e))?;
         }
-        if let Some(d) = material.dissolve {
-            writeln!(file_buf, "d {}", d).expect("Failed to write mesh");
+    }
+    writer.flush().map_err(|e| CleanerError::io(dest, e))?;
+
+    Ok(mtllibs)
+}
+
+/// Appends the MTL file source to writer, with the texture files renamed
+/// according to plan and format. Other lines are kept as they are.
+fn append_renamed_mtl(
+    source: &Path,
+    writer: &mut impl Write,
+    plan: &TexturePlan,
+    format: TextureFormat,
+) -> std::io::Result<()> {
+    let reader = BufReader::new(File::open(source)?);
+    for line in reader.split(b'\n') {
+        let mut line = line?;
+        line.push(b'\n');
+        let text = String::from_utf8_lossy(&line);
+        let (statement, texture) = split_statement(&text);
+        if is_texture_statement(statement) && !texture.is_empty() {
+            let (options, texture_file) = split_texture_options(texture);
+            let out_name = plan.out_name(texture_file, format);
+            if out_name != texture_file {
+                let options = match options.is_empty() {
+                    true => String::new(),
+                    false => format!("{options} "),
+                };
+                write!(
+                    writer,
+                    "{statement} {options}{out_name}{}",
+                    line_ending(&line)
+                )?;
+                continue;
+            }
         }
-        if let Some(ns) = material.shininess {
-            writeln!(file_buf, "Ns {}", ns).expect("Failed to write mesh");
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Copies the OBJ file source to dest with its MTL files. The geometry is
+/// copied as it is. When the OBJ file is renamed or its textures are, the
+/// MTL files are merged to one named after dest with the texture files
+/// renamed, so that other assets writing to the same folder don't share it.
+/// Otherwise they are copied under the same paths relative to dest.
+fn copy_obj(
+    source: &Path,
+    dest: &Path,
+    rename_mtl: bool,
+    plan: &TexturePlan,
+    format: TextureFormat,
+) -> Result<(), CleanerError> {
+    let source_folder = source.parent().unwrap_or(Path::new(""));
+    let dest_folder = dest.parent().unwrap_or(Path::new(""));
+    let dest_mtl = dest.with_extension("mtl");
+    let merged_mtl = rename_mtl.then(|| dest_mtl.file_name().unwrap_or_default().to_string_lossy());
+
+    let mtllibs = copy_obj_lines(source, dest, merged_mtl.as_deref())?;
+
+    if rename_mtl {
+        if mtllibs.is_empty() {
+            return Ok(());
         }
-        if let Some(illum) = material.illumination_model {
-            writeln!(file_buf, "illum {}", illum).expect("Failed to write mesh");
+        let file = File::create(&dest_mtl).map_err(|e| CleanerError::io(&dest_mtl, e))?;
+        let mut writer = BufWriter::new(file);
+        for mtllib in mtllibs.iter() {
+            let source_mtl = source_folder.join(mtllib);
+            if !source_mtl.exists() {
+                continue;
+            }
+            append_renamed_mtl(&source_mtl, &mut writer, plan, format)
+                .map_err(|e| CleanerError::io(&source_mtl, e))?;
         }
-        if let Some(map_kd) = &material.diffuse_texture {
-            writeln!(file_buf, "map_Kd {}", map_kd).expect("Failed to write mesh");
+        return writer.flush().map_err(|e| CleanerError::io(&dest_mtl, e));
+    }
 
-            // Also process the texture
-            copy_texture(map_kd, source_folder, dest_folder, texture_downscale_factor);
+    for mtllib in mtllibs.iter() {
+        let relative = Path::new(mtllib);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            println!("Not copying {mtllib:?} of {source:?}, it is outside of its folder");
+            continue;
         }
+        let source_mtl = source_folder.join(relative);
+        if !source_mtl.exists() {
+            continue;
+        }
+        let dest_mtl = dest_folder.join(relative);
+        create_parent_folder(&dest_mtl)?;
+        std::fs::copy(&source_mtl, dest_mtl).map_err(|e| CleanerError::io(&source_mtl, e))?;
     }
 
-    file_buf.flush().expect("Failed to write to disk");
+    Ok(())
 }
 
-pub trait WriteToFolder {
-    fn write_to_folder(&self, folder: &OsString);
+/// Capture date of an asset, ordered from earliest to latest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct CaptureDate([u16; 6]);
+
+impl CaptureDate {
+    /// Parses a date as `YYYY-MM-DD`, optionally followed by a time as
+    /// `THH:MM:SS` and a `Z`, e.g. `2023-05-01` or `2023-05-01T14:30:00Z`.
+    /// A date without a time is taken as midnight.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let value = value.strip_suffix('Z').unwrap_or(value);
+        let (date, time) = match value.split_once('T') {
+            Some((date, time)) => (date, Some(time)),
+            None => (value, None),
+        };
+        let fields = |value: &str, separator: char, lengths: [usize; 3]| -> Option<[u16; 3]> {
+            let parts = value.split(separator).collect::<Vec<_>>();
+            if parts.len() != 3 {
+                return None;
+            }
+            let mut fields = [0; 3];
+            for ((field, part), length) in fields.iter_mut().zip(parts).zip(lengths) {
+                if part.len() != length || !part.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                *field = part.parse().ok()?;
+            }
+            Some(fields)
+        };
+
+        let [year, month, day] = fields(date, '-', [4, 2, 2])?;
+        let [hour, minute, second] = match time {
+            Some(time) => fields(time, ':', [2, 2, 2])?,
+            None => [0; 3],
+        };
+        let valid = (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+
+        valid.then_some(Self([year, month, day, hour, minute, second]))
+    }
 }
 
-impl WriteToFolder for Model {
-    fn write_to_folder(&self, folder: &OsString) {
-        println!("Writing model to disk");
+/// Capture date of asset from the `capture_date` field of its JSON sidecar,
+/// e.g. `tile.obj.json` for `tile.obj`. None if there is no sidecar, no date
+/// in it or a date [`CaptureDate::parse`] doesn't accept.
+pub(crate) fn read_capture_date(asset: &OsStr) -> Option<CaptureDate> {
+    let mut sidecar = asset.to_os_string();
+    sidecar.push(".json");
+    let sidecar = PathBuf::from(sidecar);
+    if !sidecar.exists() {
+        return None;
+    }
 
-        let source = std::path::PathBuf::from(self.source_file.clone());
-        let source_folder = source.parent().expect("File doesnt have parent path");
-        let filename = source.file_name().expect("No filename");
+    let date = std::fs::read_to_string(&sidecar)
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|json| json["capture_date"].as_str().map(str::to_string));
+    match date {
+        Some(date) => {
+            let parsed = CaptureDate::parse(&date);
+            if parsed.is_none() {
+                println!("Invalid capture date {date:?} in {sidecar:?}, expected YYYY-MM-DD");
+            }
+            parsed
+        }
+        None => {
+            println!("No capture date in {sidecar:?}");
+            None
+        }
+    }
+}
 
-        let dest_folder = std::path::PathBuf::from(folder);
-        let dest = dest_folder.clone().join(filename);
+/// Appends `_n` to the file stem of path, e.g. `a/tile.obj` to `a/tile_2.obj`
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}_{n}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{n}"),
+    };
+    path.with_file_name(name)
+}
 
-        let mut dest_mtl = dest.clone();
-        dest_mtl.set_extension("mtl");
+/// Plans the output paths, relative to the out folder, of the assets found
+/// in each input folder. The paths mirror the input folders, and the assets
+/// of earlier folders keep their names when they collide.
+pub(crate) fn plan_out_paths(
+    scanned_folders: &[(OsString, Vec<OsString>)],
+    mode: CollisionMode,
+    format: OutputFormat,
+) -> HashMap<OsString, PathBuf> {
+    let mut out_paths = HashMap::new();
+    // Rewritten assets get the extension of the output format, so that is
+    // what the written files collide on
+    let mut taken = HashSet::new();
+    let mut subfolders = HashSet::new();
 
-        let out_obj_file = File::create(dest).expect("Unable to create file");
-        let mut out_obj_writer = BufWriter::new(out_obj_file);
+    for (folder, files) in scanned_folders {
+        let subfolder = match mode {
+            CollisionMode::Rename => PathBuf::new(),
+            CollisionMode::Subfolders => {
+                let name = Path::new(folder)
+                    .file_name()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from("input"));
+                let mut subfolder = name.clone();
+                let mut n = 0;
+                while !subfolders.insert(subfolder.clone()) {
+                    n += 1;
+                    subfolder = numbered_path(&name, n);
+                }
+                subfolder
+            }
+        };
 
-        write_header(&mut out_obj_writer);
+        for file in files {
+            let relative_path = Path::new(file)
+                .strip_prefix(folder)
+                .unwrap_or(Path::new(file));
+            let mirrored = subfolder.join(relative_path);
 
-        writeln!(
-            out_obj_writer,
-            "mtllib {}",
-            dest_mtl.file_name().unwrap().to_string_lossy()
-        )
-        .expect("Failed to write mesh");
-        writeln!(out_obj_writer).expect("Failed to write mesh");
+            let mut out_path = mirrored.clone();
+            let mut n = 0;
+            while !taken.insert(out_path.with_extension(format.extension())) {
+                n += 1;
+                out_path = numbered_path(&mirrored, n);
+            }
+            if n > 0 {
+                println!(
+                    "Output of {file:?} collides with another asset, writing it as {out_path:?}"
+                );
+            }
+
+            out_paths.insert(file.clone(), out_path);
+        }
+    }
 
-        let mut vertices = vec![];
-        let mut uvs: Vec<Vec2> = vec![];
-        let mut normals: Vec<Vec3> = vec![];
+    out_paths
+}
+
+/// Plans the texture files of out_assets so that every texture file is
+/// written by one asset only, and textures of different sources that would
+/// be written to the same file as format are numbered. Assets are planned in
+/// the order of their output paths.
+pub(crate) fn plan_textures(out_assets: &mut [OutAsset], format: TextureFormat) {
+    out_assets.sort_by(|a, b| a.out_path().cmp(b.out_path()));
+
+    // Source of each written texture file, its downscale factor and crop
+    let mut written: HashMap<PathBuf, (PathBuf, u32, Option<TextureCrop>)> = HashMap::new();
+
+    for out_asset in out_assets.iter_mut() {
+        let (source_file, out_path, materials, embedded, factor, crops) = match &*out_asset {
+            OutAsset::Asset(model) => (
+                &model.source_file,
+                &model.out_path,
+                model.meshes.iter().map(|m| &m.material).collect::<Vec<_>>(),
+                model
+                    .embedded_textures
+                    .keys()
+                    .map(|k| k.as_str())
+                    .collect::<HashSet<_>>(),
+                model.texture_downscale_factor,
+                model.texture_plan.crops.clone(),
+            ),
+            OutAsset::AssetRef(model_ref) => (
+                &model_ref.source_file,
+                &model_ref.out_path,
+                model_ref.materials.iter().collect::<Vec<_>>(),
+                model_ref
+                    .embedded_textures
+                    .iter()
+                    .map(|k| k.as_str())
+                    .collect::<HashSet<_>>(),
+                model_ref.texture_downscale_factor,
+                model_ref.texture_plan.crops.clone(),
+            ),
+        };
+        let source_folder = Path::new(source_file).parent().unwrap_or(Path::new(""));
+        let out_folder = out_path.parent().unwrap_or(Path::new(""));
+
+        let mut plan = TexturePlan {
+            crops,
+            ..Default::default()
+        };
+        let mut planned = HashSet::new();
+
+        for material in materials {
+            for texture in material_textures(material) {
+                let texture_file = split_texture_options(texture).1;
+                if !planned.insert(texture_file) {
+                    continue;
+                }
+
+                // Embedded textures are told apart by the file they are in
+                let source = match embedded.contains(texture_file) {
+                    true => Path::new(source_file).join(texture_file),
+                    false => source_folder.join(texture_file),
+                };
+                let source = (source, factor, plan.crops.get(texture_file).cloned());
 
-        for mesh in &self.meshes {
-            vertices.extend_from_slice(&mesh.mesh.positions.to_f32());
+                let mut name = texture_file.to_string();
+                let mut n = 0;
+                loop {
+                    let out_file = out_folder.join(format.file_name(&name));
+                    match written.get(&out_file) {
+                        None => {
+                            written.insert(out_file, source);
+                            break;
+                        }
+                        Some(written_source) if *written_source == source => {
+                            plan.shared.insert(texture_file.to_string());
+                            break;
+                        }
+                        Some(_) => {
+                            n += 1;
+                            name = numbered_path(Path::new(texture_file), n)
+                                .to_string_lossy()
+                                .into_owned();
+                        }
+                    }
+                }
 
-            if let Some(mesh_uvs) = &mesh.mesh.uvs {
-                uvs.extend_from_slice(mesh_uvs);
+                if n > 0 {
+                    plan.renames.insert(texture_file.to_string(), name);
+                }
             }
+        }
+
+        match out_asset {
+            OutAsset::Asset(model) => model.texture_plan = plan,
+            OutAsset::AssetRef(model_ref) => model_ref.texture_plan = plan,
+        }
+    }
+}
+
+/// Pixel region x0, y0, x1, y1 of a texture, with rows from the top
+type PixelRegion = (u32, u32, u32, u32);
+
+/// Region of each uv chart and the chart of each vertex, for each mesh
+type UvCharts = (Vec<PixelRegion>, Vec<Vec<Option<usize>>>);
+
+/// Groups the triangles of meshes to uv charts, the connected regions of an
+/// occupancy grid over the texture marked by the uv bounding box of each
+/// triangle. Returns the region of each chart in a texture of size, padded
+/// by [`CROP_MARGIN`], and the chart of each vertex a triangle uses, for each
+/// mesh. None if a mesh has no uvs or the uvs are outside the texture.
+fn uv_charts(
+    meshes: &[&MeshContainer],
+    (width, height): (u32, u32),
+) -> Result<Option<UvCharts>, CleanerError> {
+    let inside = |uv: Vec2| (0.0..=1.0).contains(&uv.x) && (0.0..=1.0).contains(&uv.y);
+    let cell = |uv: f32| ((uv * CHART_GRID_CELLS as f32) as usize).min(CHART_GRID_CELLS - 1);
 
-            if let Some(mesh_normals) = &mesh.mesh.normals {
-                normals.extend_from_slice(mesh_normals);
+    // Mesh, vertices and uv bounding box of each triangle
+    let mut triangles = Vec::new();
+    let mut occupied = vec![false; CHART_GRID_CELLS * CHART_GRID_CELLS];
+    for (mesh_idx, mesh) in meshes.iter().enumerate() {
+        let Some(uvs) = &mesh.mesh.uvs else {
+            return Ok(None);
+        };
+        for triangle in indices_u32(&mesh.mesh)?.chunks_exact(3) {
+            let mut min = Vec2::new(f32::MAX, f32::MAX);
+            let mut max = Vec2::new(f32::MIN, f32::MIN);
+            for &idx in triangle {
+                let uv = uvs[idx as usize];
+                min = Vec2::new(min.x.min(uv.x), min.y.min(uv.y));
+                max = Vec2::new(max.x.max(uv.x), max.y.max(uv.y));
+            }
+            if !inside(min) || !inside(max) {
+                return Ok(None);
+            }
+            for y in cell(min.y)..=cell(max.y) {
+                for x in cell(min.x)..=cell(max.x) {
+                    occupied[y * CHART_GRID_CELLS + x] = true;
+                }
             }
+            triangles.push((mesh_idx, triangle, min, max));
         }
+    }
 
-        for vertex in vertices.iter() {
-            writeln!(
-                out_obj_writer,
-                "v {:.15} {:.15} {:.15}",
-                vertex.x, vertex.y, vertex.z
-            )
-            .expect("Failed to write mesh");
+    // Chart of each occupied cell, by flood filling the grid
+    let mut cell_charts = vec![None; occupied.len()];
+    let mut chart_cnt = 0;
+    for start in 0..occupied.len() {
+        if !occupied[start] || cell_charts[start].is_some() {
+            continue;
+        }
+        cell_charts[start] = Some(chart_cnt);
+        let mut stack = vec![start];
+        while let Some(idx) = stack.pop() {
+            let (x, y) = (idx % CHART_GRID_CELLS, idx / CHART_GRID_CELLS);
+            let neighbours = [
+                (x > 0).then(|| idx - 1),
+                (x + 1 < CHART_GRID_CELLS).then(|| idx + 1),
+                (y > 0).then(|| idx - CHART_GRID_CELLS),
+                (y + 1 < CHART_GRID_CELLS).then(|| idx + CHART_GRID_CELLS),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if occupied[neighbour] && cell_charts[neighbour].is_none() {
+                    cell_charts[neighbour] = Some(chart_cnt);
+                    stack.push(neighbour);
+                }
+            }
         }
+        chart_cnt += 1;
+    }
 
-        for uv in uvs.iter() {
-            writeln!(out_obj_writer, "vt {:.15} {:.15}", uv.x, uv.y).expect("Failed to write mesh");
+    let mut bounds =
+        vec![(Vec2::new(f32::MAX, f32::MAX), Vec2::new(f32::MIN, f32::MIN)); chart_cnt];
+    let mut vertex_charts = meshes
+        .iter()
+        .map(|mesh| vec![None; mesh.mesh.uvs.as_ref().map_or(0, |uvs| uvs.len())])
+        .collect::<Vec<_>>();
+    for (mesh_idx, triangle, min, max) in triangles {
+        let Some(chart) = cell_charts[cell(min.y) * CHART_GRID_CELLS + cell(min.x)] else {
+            continue;
+        };
+        let (chart_min, chart_max) = &mut bounds[chart];
+        *chart_min = Vec2::new(chart_min.x.min(min.x), chart_min.y.min(min.y));
+        *chart_max = Vec2::new(chart_max.x.max(max.x), chart_max.y.max(max.y));
+        for &idx in triangle {
+            vertex_charts[mesh_idx][idx as usize] = Some(chart);
         }
+    }
 
-        for normal in normals.iter() {
-            writeln!(
-                out_obj_writer,
-                "vn {:.15} {:.15} {:.15}",
-                normal.x, normal.y, normal.z
+    // Image rows go from the top, uvs from the bottom
+    let regions = bounds
+        .into_iter()
+        .map(|(min, max)| {
+            let x0 = ((min.x * width as f32).floor() as u32).saturating_sub(CROP_MARGIN);
+            let x1 = ((max.x * width as f32).ceil() as u32 + CROP_MARGIN).min(width);
+            let y0 = (((1.0 - max.y) * height as f32).floor() as u32).saturating_sub(CROP_MARGIN);
+            let y1 = (((1.0 - min.y) * height as f32).ceil() as u32 + CROP_MARGIN).min(height);
+            (
+                x0,
+                y0,
+                x1.max(x0 + 1).min(width),
+                y1.max(y0 + 1).min(height),
             )
-            .expect("Failed to write mesh");
-        }
-
-        let mut written_vertex_cnt = 0;
-
-        for mesh in self.meshes.iter() {
-            writeln!(out_obj_writer, "g default").expect("Failed to write mesh");
-            writeln!(out_obj_writer, "usemtl {}", mesh.material.name)
-                .expect("Failed to write mesh");
-
-            mesh.mesh.for_each_triangle(|i0, i1, i2| {
-                writeln!(
-                    out_obj_writer,
-                    "f {}/{} {}/{} {}/{}",
-                    i0 + written_vertex_cnt + 1,
-                    i0 + written_vertex_cnt + 1,
-                    i1 + written_vertex_cnt + 1,
-                    i1 + written_vertex_cnt + 1,
-                    i2 + written_vertex_cnt + 1,
-                    i2 + written_vertex_cnt + 1
-                )
-                .expect("Failed to write mesh");
-            });
+        })
+        .collect();
 
-            written_vertex_cnt += mesh.mesh.positions.len();
+    Ok(Some((regions, vertex_charts)))
+}
+
+/// Packs regions to rows of a texture about as wide as it is high, the
+/// highest regions first
+fn pack_charts(regions: &[PixelRegion]) -> TextureCrop {
+    let area: u64 = regions
+        .iter()
+        .map(|&(x0, y0, x1, y1)| (x1 - x0) as u64 * (y1 - y0) as u64)
+        .sum();
+    let widest = regions.iter().map(|&(x0, _, x1, _)| x1 - x0).max();
+    let row_width = widest.unwrap_or(0).max((area as f64).sqrt().ceil() as u32);
+
+    let mut order = (0..regions.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| std::cmp::Reverse(regions[idx].3 - regions[idx].1));
+
+    let mut charts = vec![None; regions.len()];
+    let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+    for idx in order {
+        let (x0, y0, x1, y1) = regions[idx];
+        if x > 0 && x + x1 - x0 > row_width {
+            (x, y, row_height) = (0, y + row_height, 0);
         }
+        charts[idx] = Some(CropChart {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+            dest_x: x,
+            dest_y: y,
+        });
+        x += x1 - x0;
+        row_height = row_height.max(y1 - y0);
+        width = width.max(x);
+    }
 
-        out_obj_writer.flush().expect("Failed to write to disk");
+    TextureCrop {
+        width,
+        height: y + row_height,
+        charts: charts.into_iter().flatten().collect(),
+    }
+}
 
-        // Write materials
-        let materials = self.meshes.iter().map(|m| &m.material).collect::<Vec<_>>();
-        write_mtllib(
-            source_folder,
-            dest_folder.as_path(),
-            dest_mtl,
-            &materials,
-            self.texture_downscale_factor,
-        );
+/// crop of a texture for a texture scale_x and scale_y times as large
+fn scale_crop(crop: &TextureCrop, scale_x: u32, scale_y: u32) -> TextureCrop {
+    TextureCrop {
+        width: crop.width * scale_x,
+        height: crop.height * scale_y,
+        charts: crop
+            .charts
+            .iter()
+            .map(|chart| CropChart {
+                x: chart.x * scale_x,
+                y: chart.y * scale_y,
+                width: chart.width * scale_x,
+                height: chart.height * scale_y,
+                dest_x: chart.dest_x * scale_x,
+                dest_y: chart.dest_y * scale_y,
+            })
+            .collect(),
     }
 }
 
-impl WriteToFolder for ModelReference {
-    fn write_to_folder(&self, folder: &OsString) {
-        let source = std::path::PathBuf::from(self.source_file.clone());
-        let source_folder = source.parent().expect("File doesnt have parent path");
-        let filename = source.file_name().expect("No filename");
+/// Crops the textures of model to the uv charts its remaining triangles use,
+/// packed to smaller textures, and remaps the uvs of each chart to the
+/// cropped textures. Meshes sharing a texture are cropped together. Textures
+/// with offset, scale or turbulence options, textures whose size is not a
+/// multiple of the smallest one of their group and meshes with uvs outside
+/// their textures are left as they are.
+pub(crate) fn crop_textures(model: &mut Model) -> Result<(), CleanerError> {
+    let (source_folder, _) = split_source_path(&model.source_file)?;
 
-        let mut source_mtl = source.clone();
-        source_mtl.set_extension("mtl");
+    // Texture files, meshes and whether they can be cropped, for each group
+    // of meshes sharing textures
+    let mut groups: Vec<(HashSet<String>, Vec<usize>, bool)> = Vec::new();
+    for (idx, mesh) in model.meshes.iter().enumerate() {
+        let textures = material_textures(&mesh.material);
+        if textures.is_empty() {
+            continue;
+        }
 
-        let dest_folder = std::path::PathBuf::from(folder);
-        let dest = dest_folder.clone().join(filename);
+        let mut group = (HashSet::new(), vec![idx], true);
+        for texture in textures {
+            let (options, texture_file) = split_texture_options(texture);
+            group.0.insert(texture_file.to_string());
+            group.2 &= !options
+                .split_whitespace()
+                .any(|option| matches!(option, "-o" | "-s" | "-t"));
+        }
 
-        if source_mtl.exists() {
-            let mut dest_mtl = dest.clone();
-            dest_mtl.set_extension("mtl");
-            std::fs::copy(source_mtl, dest_mtl).expect("Failed to copy");
+        let (merged, rest): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(textures, _, _)| !textures.is_disjoint(&group.0));
+        groups = rest;
+        for (textures, meshes, croppable) in merged {
+            group.0.extend(textures);
+            group.1.extend(meshes);
+            group.2 &= croppable;
         }
+        groups.push(group);
+    }
 
-        println!("Copying from: {source:?}, to: {dest:?}");
-        std::fs::copy(&source, &dest).expect("Failed to copy");
+    for (textures, mesh_idxs, croppable) in groups {
+        if !croppable {
+            continue;
+        }
 
-        for material in &self.materials {
-            let textures = vec![
-                &material.diffuse_texture,
-                &material.ambient_texture,
-                &material.dissolve_texture,
-                &material.specular_texture,
-                &material.normal_texture,
-                &material.shininess_texture,
-            ];
+        let mut sizes = Vec::new();
+        for texture_file in textures {
+            let texture_src = source_folder.join(&texture_file);
+            let data = read_texture(&texture_file, source_folder, &model.embedded_textures)?;
+            let size = ImageReader::new(Cursor::new(data))
+                .with_guessed_format()
+                .map_err(|e| CleanerError::io(&texture_src, e))?
+                .into_dimensions()
+                .map_err(|source| CleanerError::Image {
+                    path: texture_src,
+                    source,
+                })?;
+            sizes.push((texture_file, size));
+        }
 
-            for texture_file in textures.into_iter().flatten() {
-                copy_texture(
-                    texture_file,
-                    source_folder,
-                    &dest_folder,
-                    self.texture_downscale_factor,
-                );
+        // The charts are found on the pixels of the smallest texture and
+        // scaled to the others
+        let Some(&(_, (width, height))) = sizes
+            .iter()
+            .min_by_key(|(_, (width, height))| *width as u64 * *height as u64)
+        else {
+            continue;
+        };
+        if let Some((texture_file, _)) = sizes
+            .iter()
+            .find(|(_, (w, h))| w % width != 0 || h % height != 0)
+        {
+            println!(
+                "Not cropping {texture_file:?} of {:?}, its size is not a multiple of the \
+                 other textures of its meshes",
+                model.source_file
+            );
+            continue;
+        }
+
+        let meshes = mesh_idxs
+            .iter()
+            .map(|&idx| &model.meshes[idx])
+            .collect::<Vec<_>>();
+        let Some((regions, vertex_charts)) = uv_charts(&meshes, (width, height))? else {
+            continue;
+        };
+
+        // Charts next to each other can pack worse than the region around
+        // all of them
+        let bbox = regions
+            .iter()
+            .fold((width, height, 0, 0), |(x0, y0, x1, y1), region| {
+                (
+                    x0.min(region.0),
+                    y0.min(region.1),
+                    x1.max(region.2),
+                    y1.max(region.3),
+                )
+            });
+        let mut crop = pack_charts(&regions);
+        let single = pack_charts(&[bbox]);
+        let merged =
+            single.width as u64 * single.height as u64 <= crop.width as u64 * crop.height as u64;
+        if merged {
+            crop = single;
+        }
+
+        let area = crop.width as u64 * crop.height as u64;
+        if area >= width as u64 * height as u64 {
+            continue;
+        }
+        let saving = 1.0 - area as f32 / (width as f32 * height as f32);
+        if saving < SMALL_CROP_SAVING {
+            let textures = sizes.iter().map(|(file, _)| file).collect::<Vec<_>>();
+            println!(
+                "Cropping {textures:?} of {:?} only saves {:.0} % of their area",
+                model.source_file,
+                saving * 100.0
+            );
+        }
+
+        for (texture_file, (texture_width, texture_height)) in sizes {
+            let texture_crop = scale_crop(&crop, texture_width / width, texture_height / height);
+            model.texture_plan.crops.insert(texture_file, texture_crop);
+        }
+
+        for (idx, charts) in mesh_idxs.into_iter().zip(vertex_charts) {
+            let Some(uvs) = &mut model.meshes[idx].mesh.uvs else {
+                continue;
+            };
+            for (uv, chart) in uvs.iter_mut().zip(charts) {
+                let Some(chart) = chart else {
+                    continue;
+                };
+                let chart = crop.charts[if merged { 0 } else { chart }];
+                let x = uv.x * width as f32 - chart.x as f32 + chart.dest_x as f32;
+                let y = (1.0 - uv.y) * height as f32 - chart.y as f32 + chart.dest_y as f32;
+                *uv = Vec2::new(x / crop.width as f32, 1.0 - y / crop.height as f32);
             }
         }
     }
+
+    Ok(())
 }
 
-impl WriteToFolder for OutAsset {
-    fn write_to_folder(&self, folder: &OsString) {
-        match self {
-            OutAsset::Asset(model) => model.write_to_folder(folder),
-            OutAsset::AssetRef(model_ref) => model_ref.write_to_folder(folder),
+/// Copy of material with the texture files renamed according to plan and
+/// format
+fn rename_textures(
+    material: &tobj::Material,
+    plan: &TexturePlan,
+    format: TextureFormat,
+) -> tobj::Material {
+    let rename = |texture: &mut String| {
+        let (options, texture_file) = split_texture_options(texture);
+        let out_name = plan.out_name(texture_file, format);
+        if out_name != texture_file {
+            *texture = match options.is_empty() {
+                true => out_name,
+                false => format!("{options} {out_name}"),
+            };
+        }
+    };
+
+    let mut material = material.clone();
+    for texture in [
+        &mut material.ambient_texture,
+        &mut material.diffuse_texture,
+        &mut material.specular_texture,
+        &mut material.normal_texture,
+        &mut material.shininess_texture,
+        &mut material.dissolve_texture,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        rename(texture);
+    }
+    for (statement, value) in material.unknown_param.iter_mut() {
+        if is_texture_statement(statement) {
+            rename(value);
+        }
+    }
+    material
+}
+
+/// Creates the folder dest is written to, returning it
+fn create_parent_folder(dest: &Path) -> Result<PathBuf, CleanerError> {
+    let dest_folder = dest
+        .parent()
+        .ok_or_else(|| CleanerError::InvalidPath(dest.as_os_str().to_owned()))?;
+    std::fs::create_dir_all(dest_folder).map_err(|e| CleanerError::io(dest_folder, e))?;
+    Ok(dest_folder.to_path_buf())
+}
+
+/// Whether an MTL statement stored in the unknown parameters of a tobj
+/// material refers to a texture
+fn is_texture_statement(statement: &str) -> bool {
+    statement.to_ascii_lowercase().starts_with("map_")
+        || matches!(statement, "bump" | "disp" | "decal" | "refl" | "norm")
+}
+
+/// All texture statement values of material, including the ones tobj
+/// doesn't parse
+pub(crate) fn material_textures(material: &tobj::Material) -> Vec<&str> {
+    let mut textures = [
+        &material.ambient_texture,
+        &material.diffuse_texture,
+        &material.specular_texture,
+        &material.normal_texture,
+        &material.shininess_texture,
+        &material.dissolve_texture,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|t| t.as_str())
+    .collect::<Vec<_>>();
+
+    let mut unknown_textures = material
+        .unknown_param
+        .iter()
+        .filter(|(statement, _)| is_texture_statement(statement))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+    unknown_textures.sort();
+    textures.extend(unknown_textures);
+
+    textures
+}
+
+/// Splits a texture statement value to its options and the texture file,
+/// e.g. `-s 2 2 1 -clamp on stone.png` to `-s 2 2 1 -clamp on` and
+/// `stone.png`. The file name may contain spaces.
+pub(crate) fn split_texture_options(texture: &str) -> (&str, &str) {
+    let next_token = |s: &str| s.find(char::is_whitespace).unwrap_or(s.len());
+
+    let mut rest = texture.trim_start();
+    loop {
+        let token_end = next_token(rest);
+        let option = &rest[..token_end];
+        // The last token is always the file name
+        if !option.starts_with('-') || token_end == rest.len() {
+            break;
+        }
+
+        let (min_args, max_args) = match option {
+            "-mm" => (2, 2),
+            "-o" | "-s" | "-t" => (1, 3),
+            _ => (1, 1),
+        };
+        rest = rest[token_end..].trim_start();
+
+        for i in 0..max_args {
+            let arg_end = next_token(rest);
+            if arg_end == rest.len() || (i >= min_args && rest[..arg_end].parse::<f32>().is_err()) {
+                break;
+            }
+            rest = rest[arg_end..].trim_start();
         }
     }
+
+    let file_start = texture.len() - rest.len();
+    (texture[..file_start].trim(), rest.trim_end())
+}
+
+/// Reads the encoded texture either from the textures embedded in the
+/// source file or from the source folder
+fn read_texture(
+    texture_file: &str,
+    source_folder: &Path,
+    embedded_textures: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, CleanerError> {
+    if let Some(data) = embedded_textures.get(texture_file) {
+        return Ok(data.clone());
+    }
+
+    let texture_src = source_folder.join(texture_file);
+    if !texture_src.exists() {
+        return Err(CleanerError::MissingTexture(texture_src));
+    }
+    std::fs::read(&texture_src).map_err(|e| CleanerError::io(&texture_src, e))
+}
+
+fn decode_texture(data: &[u8], texture_src: &Path) -> Result<DynamicImage, CleanerError> {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| CleanerError::io(texture_src, e))?
+        .decode()
+        .map_err(|source| CleanerError::Image {
+            path: texture_src.to_path_buf(),
+            source,
+        })
+}
+
+/// Crops img to crop and downscales it by downscale_factor
+fn resize_texture(
+    img: DynamicImage,
+    crop: Option<&TextureCrop>,
+    downscale_factor: u32,
+) -> DynamicImage {
+    let img = match crop {
+        Some(crop) => {
+            let mut cropped = DynamicImage::new(crop.width, crop.height, img.color());
+            for chart in &crop.charts {
+                let region = img.crop_imm(chart.x, chart.y, chart.width, chart.height);
+                image::imageops::replace(
+                    &mut cropped,
+                    &region,
+                    chart.dest_x as i64,
+                    chart.dest_y as i64,
+                );
+            }
+            cropped
+        }
+        None => img,
+    };
+    if downscale_factor == 1 {
+        return img;
+    }
+    img.resize_exact(
+        img.width() / downscale_factor,
+        img.height() / downscale_factor,
+        image::imageops::FilterType::Triangle,
+    )
+}
+
+/// Encodes img in the format of encoding, PNG when it keeps the source format
+fn encode_texture(
+    img: &DynamicImage,
+    encoding: TextureEncoding,
+    texture_dst: &Path,
+) -> Result<Vec<u8>, CleanerError> {
+    let mut encoded = Cursor::new(Vec::new());
+    let result = match encoding.format {
+        // JPEG has no alpha channel
+        TextureFormat::Jpeg => img
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(
+                &mut encoded,
+                encoding.jpeg_quality,
+            )),
+        TextureFormat::Webp if img.color().has_alpha() => img
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut encoded)),
+        TextureFormat::Webp => img
+            .to_rgb8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut encoded)),
+        TextureFormat::Keep | TextureFormat::Png => img.write_to(&mut encoded, ImageFormat::Png),
+    };
+    result.map_err(|source| CleanerError::Image {
+        path: texture_dst.to_path_buf(),
+        source,
+    })?;
+    Ok(encoded.into_inner())
+}
+
+/// Copies the texture file of a texture statement value from source folder
+/// or the embedded textures to dest folder, named and cropped according to
+/// plan and transcoded according to encoding
+fn copy_texture(
+    texture: &str,
+    source_folder: &Path,
+    dest_folder: &Path,
+    embedded_textures: &HashMap<String, Vec<u8>>,
+    downscale_factor: u32,
+    plan: &TexturePlan,
+    encoding: TextureEncoding,
+) -> Result<(), CleanerError> {
+    let texture_file = split_texture_options(texture).1;
+    if plan.shared.contains(texture_file) {
+        return Ok(());
+    }
+
+    let texture_src = source_folder.join(texture_file);
+    let texture_dst = dest_folder.join(plan.out_name(texture_file, encoding.format));
+    create_parent_folder(&texture_dst)?;
+
+    let crop = plan.crops.get(texture_file);
+    let keep_data = downscale_factor == 1 && crop.is_none();
+    let keep_format = encoding.format == TextureFormat::Keep;
+
+    if keep_data && keep_format && !embedded_textures.contains_key(texture_file) {
+        if !texture_src.exists() {
+            return Err(CleanerError::MissingTexture(texture_src));
+        }
+        std::fs::copy(&texture_src, &texture_dst).map_err(|e| CleanerError::io(&texture_src, e))?;
+        return Ok(());
+    }
+
+    let data = read_texture(texture_file, source_folder, embedded_textures)?;
+    if keep_data && keep_format {
+        std::fs::write(&texture_dst, data).map_err(|e| CleanerError::io(&texture_dst, e))?;
+        return Ok(());
+    }
+
+    let img = resize_texture(decode_texture(&data, &texture_src)?, crop, downscale_factor);
+    if keep_format {
+        img.save(&texture_dst)
+            .map_err(|source| CleanerError::Image {
+                path: texture_dst.clone(),
+                source,
+            })?;
+    } else {
+        let encoded = encode_texture(&img, encoding, &texture_dst)?;
+        std::fs::write(&texture_dst, encoded).map_err(|e| CleanerError::io(&texture_dst, e))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the encoded texture and its mime type for storing in a GLB file.
+/// Textures are encoded as JPEG or PNG when encoding asks for it. Otherwise
+/// the ones that are downscaled, cropped or not PNG or JPEG are encoded as
+/// PNG.
+fn embed_texture(
+    texture: &str,
+    source_folder: &Path,
+    embedded_textures: &HashMap<String, Vec<u8>>,
+    downscale_factor: u32,
+    plan: &TexturePlan,
+    encoding: TextureEncoding,
+) -> Result<GlbImage, CleanerError> {
+    let texture_file = split_texture_options(texture).1;
+    let crop = plan.crops.get(texture_file);
+    let texture_src = source_folder.join(texture_file);
+    let data = read_texture(texture_file, source_folder, embedded_textures)?;
+
+    let mime_type = match image::guess_format(&data) {
+        Ok(ImageFormat::Png) => Some("image/png"),
+        Ok(ImageFormat::Jpeg) => Some("image/jpeg"),
+        _ => None,
+    };
+    let transcode = matches!(encoding.format, TextureFormat::Png | TextureFormat::Jpeg);
+    if downscale_factor == 1
+        && crop.is_none()
+        && !transcode
+        && let Some(mime_type) = mime_type
+    {
+        return Ok(GlbImage::Embedded {
+            data,
+            mime_type: mime_type.to_string(),
+        });
+    }
+
+    // glTF only supports PNG and JPEG
+    let (format, mime_type) = match encoding.format {
+        TextureFormat::Jpeg => (TextureFormat::Jpeg, "image/jpeg"),
+        _ => (TextureFormat::Png, "image/png"),
+    };
+    let img = resize_texture(decode_texture(&data, &texture_src)?, crop, downscale_factor);
+    let data = encode_texture(&img, TextureEncoding { format, ..encoding }, &texture_src)?;
+
+    Ok(GlbImage::Embedded {
+        data,
+        mime_type: mime_type.to_string(),
+    })
+}
+
+fn write_header(writer: &mut impl Write) -> std::io::Result<()> {
+    writeln!(writer, "#")?;
+    writeln!(writer, "# Wavefront OBJ file")?;
+    writeln!(writer, "# Created by obj-overlap-cleaner")?;
+    writeln!(writer, "# https://github.com/sanox-oy/obj-overlap-cleaner")?;
+    writeln!(writer, "#")
+}
+
+fn write_materials(writer: &mut impl Write, materials: &[&tobj::Material]) -> std::io::Result<()> {
+    write_header(writer)?;
+
+    // Meshes can share materials, write each of them once
+    let mut written = HashSet::new();
+
+    for material in materials {
+        if !written.insert(&material.name) {
+            continue;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "newmtl {}", material.name)?;
+        if let Some(ka) = material.ambient {
+            writeln!(writer, "Ka {} {} {}", ka[0], ka[1], ka[2])?;
+        }
+        if let Some(kd) = material.diffuse {
+            writeln!(writer, "Kd {} {} {}", kd[0], kd[1], kd[2])?;
+        }
+        if let Some(ks) = material.specular {
+            writeln!(writer, "Ks {} {} {}", ks[0], ks[1], ks[2])?;
+        }
+        if let Some(ns) = material.shininess {
+            writeln!(writer, "Ns {}", ns)?;
+        }
+        if let Some(ni) = material.optical_density {
+            writeln!(writer, "Ni {}", ni)?;
+        }
+        if let Some(d) = material.dissolve {
+            writeln!(writer, "d {}", d)?;
+        }
+        if let Some(illum) = material.illumination_model {
+            writeln!(writer, "illum {}", illum)?;
+        }
+
+        // Texture values include their options, so they are written as is
+        let maps = [
+            ("map_Ka", &material.ambient_texture),
+            ("map_Kd", &material.diffuse_texture),
+            ("map_Ks", &material.specular_texture),
+            ("map_Ns", &material.shininess_texture),
+            ("map_d", &material.dissolve_texture),
+            ("map_Bump", &material.normal_texture),
+        ];
+        for (statement, texture) in maps {
+            if let Some(texture) = texture {
+                writeln!(writer, "{} {}", statement, texture)?;
+            }
+        }
+
+        // Sorted for a deterministic output
+        let mut unknown_params = material.unknown_param.iter().collect::<Vec<_>>();
+        unknown_params.sort();
+        for (statement, value) in unknown_params {
+            writeln!(writer, "{} {}", statement, value)?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Writes materials to dest and their textures next to it
+fn write_mtllib(
+    source_folder: &Path,
+    dest: PathBuf,
+    materials: &[&tobj::Material],
+    embedded_textures: &HashMap<String, Vec<u8>>,
+    texture_downscale_factor: u32,
+    texture_plan: &TexturePlan,
+    texture_encoding: TextureEncoding,
+) -> Result<(), CleanerError> {
+    let dest_folder = dest.parent().unwrap_or(Path::new(""));
+    let file = File::create(&dest).map_err(|e| CleanerError::io(&dest, e))?;
+    let mut file_buf = BufWriter::new(file);
+
+    let renamed = materials
+        .iter()
+        .map(|material| rename_textures(material, texture_plan, texture_encoding.format))
+        .collect::<Vec<_>>();
+    let renamed = renamed.iter().collect::<Vec<_>>();
+    write_materials(&mut file_buf, &renamed).map_err(|e| CleanerError::io(&dest, e))?;
+
+    // Also process the textures
+    for material in materials {
+        for texture in material_textures(material) {
+            copy_texture(
+                texture,
+                source_folder,
+                dest_folder,
+                embedded_textures,
+                texture_downscale_factor,
+                texture_plan,
+                texture_encoding,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub trait WriteToFolder {
+    fn write_to_folder(
+        &self,
+        folder: &OsString,
+        options: &WriteOptions,
+    ) -> Result<(), CleanerError>;
+}
+
+impl WriteToFolder for Model {
+    fn write_to_folder(
+        &self,
+        folder: &OsString,
+        options: &WriteOptions,
+    ) -> Result<(), CleanerError> {
+        println!("Writing model to disk");
+
+        let (source_folder, _) = split_source_path(&self.source_file)?;
+
+        let mut dest = PathBuf::from(folder).join(&self.out_path);
+        let dest_folder = create_parent_folder(&dest)?;
+
+        match options.format {
+            OutputFormat::Obj => {
+                dest.set_extension("obj");
+                let mut dest_mtl = dest.clone();
+                dest_mtl.set_extension("mtl");
+
+                let out_obj_file = File::create(&dest).map_err(|e| CleanerError::io(&dest, e))?;
+                let mut out_obj_writer = BufWriter::new(out_obj_file);
+
+                write_obj(&mut out_obj_writer, self, &dest_mtl)
+                    .map_err(|e| CleanerError::io(&dest, e))?;
+
+                // Write materials
+                let materials = self.meshes.iter().map(|m| &m.material).collect::<Vec<_>>();
+                write_mtllib(
+                    source_folder,
+                    dest_mtl,
+                    &materials,
+                    &self.embedded_textures,
+                    self.texture_downscale_factor,
+                    &self.texture_plan,
+                    options.texture_encoding,
+                )
+            }
+            OutputFormat::Glb => {
+                dest.set_extension("glb");
+
+                // Only the textures that are referenced from GLB materials
+                let mut images = HashMap::new();
+                for material in self.meshes.iter().map(|m| &m.material) {
+                    let textures = [&material.diffuse_texture, &material.normal_texture];
+                    for texture_file in textures.into_iter().flatten() {
+                        if images.contains_key(texture_file) {
+                            continue;
+                        }
+                        let image = if options.embed_textures {
+                            embed_texture(
+                                texture_file,
+                                source_folder,
+                                &self.embedded_textures,
+                                self.texture_downscale_factor,
+                                &self.texture_plan,
+                                options.texture_encoding,
+                            )?
+                        } else {
+                            copy_texture(
+                                texture_file,
+                                source_folder,
+                                &dest_folder,
+                                &self.embedded_textures,
+                                self.texture_downscale_factor,
+                                &self.texture_plan,
+                                options.texture_encoding,
+                            )?;
+                            let texture_file = split_texture_options(texture_file).1;
+                            GlbImage::External(
+                                self.texture_plan
+                                    .out_name(texture_file, options.texture_encoding.format),
+                            )
+                        };
+                        images.insert(texture_file.clone(), image);
+                    }
+                }
+
+                gltf_io::write_glb(&dest, self, &images)
+            }
+        }
+    }
+}
+
+fn write_obj(
+    out_obj_writer: &mut impl Write,
+    model: &Model,
+    dest_mtl: &Path,
+) -> std::io::Result<()> {
+    write_header(out_obj_writer)?;
+
+    writeln!(
+        out_obj_writer,
+        "mtllib {}",
+        dest_mtl.file_name().unwrap_or_default().to_string_lossy()
+    )?;
+    writeln!(out_obj_writer)?;
+
+    let mut vertices = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    for mesh in &model.meshes {
+        vertices.extend_from_slice(&mesh.mesh.positions.to_f32());
+
+        if let Some(mesh_uvs) = &mesh.mesh.uvs {
+            uvs.extend_from_slice(mesh_uvs);
+        }
+
+        if let Some(mesh_normals) = &mesh.mesh.normals {
+            normals.extend_from_slice(mesh_normals);
+        }
+    }
+
+    for vertex in vertices.iter() {
+        writeln!(
+            out_obj_writer,
+            "v {:.15} {:.15} {:.15}",
+            vertex.x, vertex.y, vertex.z
+        )?;
+    }
+
+    for uv in uvs.iter() {
+        writeln!(out_obj_writer, "vt {:.15} {:.15}", uv.x, uv.y)?;
+    }
+
+    for normal in normals.iter() {
+        writeln!(
+            out_obj_writer,
+            "vn {:.15} {:.15} {:.15}",
+            normal.x, normal.y, normal.z
+        )?;
+    }
+
+    // Meshes can lack uvs or normals, so each attribute has its own offset
+    let mut written_vertex_cnt = 0;
+    let mut written_uv_cnt = 0;
+    let mut written_normal_cnt = 0;
+    let mut current_object = None;
+
+    for mesh in model.meshes.iter() {
+        let names = &mesh.names;
+        // Readers name meshes after the last o or g statement, so the object
+        // is repeated for meshes that aren't in a group
+        if let Some(object) = &names.object
+            && (current_object != Some(object) || names.group.is_none())
+        {
+            writeln!(out_obj_writer, "o {}", object)?;
+        }
+        current_object = names.object.as_ref();
+
+        match (&names.object, &names.group) {
+            (_, Some(group)) => writeln!(out_obj_writer, "g {}", group)?,
+            (None, None) => writeln!(out_obj_writer, "g default")?,
+            (Some(_), None) => {}
+        }
+        writeln!(out_obj_writer, "usemtl {}", mesh.material.name)?;
+
+        let has_uvs = mesh.mesh.uvs.is_some();
+        let has_normals = mesh.mesh.normals.is_some();
+        let face_vertex = |i: usize| {
+            let v = i + written_vertex_cnt + 1;
+            let vt = i + written_uv_cnt + 1;
+            let vn = i + written_normal_cnt + 1;
+            match (has_uvs, has_normals) {
+                (false, false) => format!("{v}"),
+                (true, false) => format!("{v}/{vt}"),
+                (false, true) => format!("{v}//{vn}"),
+                (true, true) => format!("{v}/{vt}/{vn}"),
+            }
+        };
+
+        let mut result = Ok(());
+        mesh.mesh.for_each_triangle(|i0, i1, i2| {
+            if result.is_err() {
+                return;
+            }
+            result = writeln!(
+                out_obj_writer,
+                "f {} {} {}",
+                face_vertex(i0),
+                face_vertex(i1),
+                face_vertex(i2)
+            );
+        });
+        result?;
+
+        written_vertex_cnt += mesh.mesh.positions.len();
+        written_uv_cnt += mesh.mesh.uvs.as_ref().map_or(0, |uvs| uvs.len());
+        written_normal_cnt += mesh
+            .mesh
+            .normals
+            .as_ref()
+            .map_or(0, |normals| normals.len());
+    }
+
+    out_obj_writer.flush()
+}
+
+/// Writes model as OBJ into folder with the vertices coloured by their
+/// [`VertexClass`], for inspecting the cleaning decisions in e.g. MeshLab.
+/// Must be called before the overlapping geometry is cut.
+pub(crate) fn write_debug_obj(
+    folder: &OsString,
+    model: &Model,
+    cut_mode: CutMode,
+) -> Result<(), CleanerError> {
+    let mut dest = PathBuf::from(folder).join(&model.out_path);
+    dest.set_extension("obj");
+    create_parent_folder(&dest)?;
+
+    let file = File::create(&dest).map_err(|e| CleanerError::io(&dest, e))?;
+    let mut writer = BufWriter::new(file);
+    write_vertex_classes(&mut writer, model, cut_mode).map_err(|e| CleanerError::io(&dest, e))
+}
+
+fn write_vertex_classes(
+    writer: &mut impl Write,
+    model: &Model,
+    cut_mode: CutMode,
+) -> std::io::Result<()> {
+    write_header(writer)?;
+
+    writeln!(writer, "# Vertex colours:")?;
+    for class in [
+        VertexClass::Kept,
+        VertexClass::Overlapping,
+        VertexClass::Deleted,
+        VertexClass::Island,
+        VertexClass::BoundaryKept,
+    ] {
+        let [r, g, b] = class.color();
+        writeln!(writer, "#   {class:?}: {r} {g} {b}")?;
+    }
+
+    let mut written_vertex_cnt = 0;
+
+    for mesh in model.meshes.iter() {
+        writeln!(writer)?;
+        writeln!(writer, "o {}", mesh.display_name())?;
+
+        let vertices = mesh.mesh.positions.to_f32();
+        for (vertex, class) in vertices.iter().zip(mesh.vertex_classes(cut_mode)) {
+            let [r, g, b] = class.color();
+            writeln!(
+                writer,
+                "v {:.15} {:.15} {:.15} {r} {g} {b}",
+                vertex.x, vertex.y, vertex.z
+            )?;
+        }
+
+        let indices = mesh.mesh.indices.to_u32().unwrap_or_default();
+        for tri in indices.chunks_exact(3) {
+            writeln!(
+                writer,
+                "f {} {} {}",
+                tri[0] as usize + written_vertex_cnt + 1,
+                tri[1] as usize + written_vertex_cnt + 1,
+                tri[2] as usize + written_vertex_cnt + 1
+            )?;
+        }
+
+        written_vertex_cnt += vertices.len();
+    }
+
+    Ok(())
+}
+
+impl WriteToFolder for ModelReference {
+    fn write_to_folder(
+        &self,
+        folder: &OsString,
+        options: &WriteOptions,
+    ) -> Result<(), CleanerError> {
+        // OBJ files are copied with only their file names changed and their
+        // textures transcoded. Other files are only copied when they can be
+        // used as they are, everything else is loaded and written in the
+        // output format.
+        let renamed = Path::new(&self.source_file).file_stem() != self.out_path.file_stem();
+        let textures_renamed = !self.texture_plan.renames.is_empty()
+            || options.texture_encoding.format != TextureFormat::Keep;
+        let copy_through = match AssetFormat::from_path(&self.source_file) {
+            Some(AssetFormat::Obj) => options.format == OutputFormat::Obj,
+            Some(AssetFormat::Glb) => {
+                options.format == OutputFormat::Glb
+                    && self.texture_downscale_factor == 1
+                    && !textures_renamed
+            }
+            _ => false,
+        };
+        if !copy_through {
+            let mut model = Model::try_new_from_file(
+                self.source_file.clone(),
+                false,
+                None,
+                self.texture_downscale_factor,
+            )?;
+            model.out_path = self.out_path.clone();
+            model.texture_plan = self.texture_plan.clone();
+            return model.write_to_folder(folder, options);
+        }
+
+        let source = std::path::PathBuf::from(self.source_file.clone());
+        let (source_folder, _) = split_source_path(&self.source_file)?;
+
+        let dest = PathBuf::from(folder).join(&self.out_path);
+        let dest_folder = create_parent_folder(&dest)?;
+
+        println!("Copying from: {source:?}, to: {dest:?}");
+        match options.format {
+            OutputFormat::Obj => copy_obj(
+                &source,
+                &dest,
+                renamed || textures_renamed,
+                &self.texture_plan,
+                options.texture_encoding.format,
+            )?,
+            OutputFormat::Glb => {
+                std::fs::copy(&source, &dest).map_err(|e| CleanerError::io(&source, e))?;
+            }
+        }
+
+        for material in &self.materials {
+            for texture_file in material_textures(material) {
+                if self.embedded_textures.contains(texture_file) {
+                    continue;
+                }
+                copy_texture(
+                    texture_file,
+                    source_folder,
+                    &dest_folder,
+                    &HashMap::new(),
+                    self.texture_downscale_factor,
+                    &self.texture_plan,
+                    options.texture_encoding,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteToFolder for OutAsset {
+    fn write_to_folder(
+        &self,
+        folder: &OsString,
+        options: &WriteOptions,
+    ) -> Result<(), CleanerError> {
+        match self {
+            OutAsset::Asset(model) => model.write_to_folder(folder, options),
+            OutAsset::AssetRef(model_ref) => model_ref.write_to_folder(folder, options),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{MeshContainer, MeshNames},
+        test_utils::TempDir,
+    };
+
+    #[test]
+    fn test_scan_folder_for_assets() {
+        let dir = TempDir::new("scan_folder");
+        for file in [
+            "a.obj",
+            "readme.txt",
+            "Tile_1/b.obj",
+            "Tile_1/skip/c.obj",
+            "Tile_2/d.glb",
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let scan = |include: &[&str], exclude: &[&str]| {
+            let to_strings =
+                |globs: &[&str]| globs.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+            let filter = AssetFilter::new(&to_strings(include), &to_strings(exclude)).unwrap();
+            scan_folder_for_assets(&dir.as_os_str().into(), &filter)
+                .unwrap()
+                .iter()
+                .map(|p| {
+                    let relative = Path::new(p).strip_prefix(&*dir).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let all = scan(&[], &[]);
+        let names = scan(&["*.obj"], &["skip"]);
+        let paths = scan(&["Tile_*/*"], &[]);
+
+        assert_eq!(
+            all,
+            ["Tile_1/b.obj", "Tile_1/skip/c.obj", "Tile_2/d.glb", "a.obj"]
+        );
+        assert_eq!(names, ["Tile_1/b.obj", "a.obj"]);
+        assert_eq!(paths, ["Tile_1/b.obj", "Tile_2/d.glb"]);
+        assert!(AssetFilter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_plan_out_paths() {
+        let scanned_folders = vec![
+            (
+                OsString::from("normal"),
+                vec!["normal/a/tile.glb".into(), "normal/a/tile.obj".into()],
+            ),
+            (OsString::from("x/hq"), vec!["x/hq/a/tile.obj".into()]),
+            (OsString::from("y/hq"), vec!["y/hq/b.obj".into()]),
+        ];
+        let out_path = |out_paths: &HashMap<OsString, PathBuf>, file: &str| {
+            out_paths[&OsString::from(file)]
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+
+        let renamed = plan_out_paths(&scanned_folders, CollisionMode::Rename, OutputFormat::Obj);
+        assert_eq!(out_path(&renamed, "normal/a/tile.glb"), "a/tile.glb");
+        assert_eq!(out_path(&renamed, "normal/a/tile.obj"), "a/tile_1.obj");
+        assert_eq!(out_path(&renamed, "x/hq/a/tile.obj"), "a/tile_2.obj");
+        assert_eq!(out_path(&renamed, "y/hq/b.obj"), "b.obj");
+
+        let subfolders = plan_out_paths(
+            &scanned_folders,
+            CollisionMode::Subfolders,
+            OutputFormat::Glb,
+        );
+        assert_eq!(
+            out_path(&subfolders, "normal/a/tile.glb"),
+            "normal/a/tile.glb"
+        );
+        assert_eq!(
+            out_path(&subfolders, "normal/a/tile.obj"),
+            "normal/a/tile_1.obj"
+        );
+        assert_eq!(out_path(&subfolders, "x/hq/a/tile.obj"), "hq/a/tile.obj");
+        assert_eq!(out_path(&subfolders, "y/hq/b.obj"), "hq_1/b.obj");
+    }
+
+    #[test]
+    fn test_copy_through_keeps_mtllibs() {
+        let dir = TempDir::new("copy_through");
+        let source = dir.join("in/tile.obj");
+        std::fs::create_dir_all(dir.join("in/sub")).unwrap();
+        std::fs::write(
+            &source,
+            "mtllib shared materials.mtl\nmtllib sub/extra.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("in/shared materials.mtl"), "newmtl red\n").unwrap();
+        std::fs::write(dir.join("in/sub/extra.mtl"), "newmtl blue\n").unwrap();
+
+        let model = Model::try_new_from_file(source.into(), false, None, 1).unwrap();
+        ModelReference::from_model(&model, 1)
+            .write_to_folder(&dir.join("out").into(), &WriteOptions::default())
+            .unwrap();
+
+        assert!(dir.join("out/tile.obj").exists());
+        assert!(dir.join("out/shared materials.mtl").exists());
+        assert!(dir.join("out/sub/extra.mtl").exists());
+        assert!(!dir.join("out/tile.mtl").exists());
+    }
+
+    #[test]
+    fn test_renamed_copy_keeps_geometry() {
+        let dir = TempDir::new("renamed_copy");
+        let source = dir.join("in/tile.obj");
+        std::fs::create_dir_all(dir.join("in")).unwrap();
+        let obj = "# comment\r\nmtllib a.mtl\r\nmtllib b.mtl\r\n\
+                   v 0 0 0 1 0 0\r\nv 1 0 0 0 1 0\r\nv 1 1 0 0 0 1\r\nv 0 1 0 1 1 1\r\n\
+                   usemtl red\r\nf 1 2 3 4\r\nl 1 3\r\n";
+        std::fs::write(&source, obj).unwrap();
+        std::fs::write(dir.join("in/a.mtl"), "newmtl red\nmap_Kd -bm 0.5 tex.png\n").unwrap();
+        std::fs::write(dir.join("in/b.mtl"), "newmtl blue\nNs 10\nmap_Ka tex.png\n").unwrap();
+        image::RgbImage::new(4, 4)
+            .save(dir.join("in/tex.png"))
+            .unwrap();
+
+        let model = Model::try_new_from_file(source.into(), false, None, 1).unwrap();
+        let mut model_ref = ModelReference::from_model(&model, 1);
+        model_ref.out_path = "tile_1.obj".into();
+        model_ref
+            .texture_plan
+            .renames
+            .insert("tex.png".to_string(), "tex_1.png".to_string());
+        model_ref
+            .write_to_folder(&dir.join("out").into(), &WriteOptions::default())
+            .unwrap();
+
+        let written = std::fs::read_to_string(dir.join("out/tile_1.obj")).unwrap();
+        assert_eq!(
+            written,
+            obj.replace("mtllib a.mtl\r\nmtllib b.mtl", "mtllib tile_1.mtl")
+        );
+        let mtl = std::fs::read_to_string(dir.join("out/tile_1.mtl")).unwrap();
+        assert_eq!(
+            mtl,
+            "newmtl red\nmap_Kd -bm 0.5 tex_1.png\nnewmtl blue\nNs 10\nmap_Ka tex_1.png\n"
+        );
+        assert!(dir.join("out/tex_1.png").exists());
+        assert!(!dir.join("out/a.mtl").exists());
+        assert!(!dir.join("out/tex.png").exists());
+    }
+
+    #[test]
+    fn test_transcoded_copy_keeps_geometry() {
+        let dir = TempDir::new("transcoded_copy");
+        let source = dir.join("in/tile.obj");
+        std::fs::create_dir_all(dir.join("in")).unwrap();
+        let obj = "mtllib tile.mtl\nv 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 1 1 0 0 0 1\n\
+                   v 0 1 0 1 1 1\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   usemtl red\nf 1/1 2/2 3/3 4/4\n";
+        std::fs::write(&source, obj).unwrap();
+        std::fs::write(
+            dir.join("in/tile.mtl"),
+            "# red\nnewmtl red\nmap_Kd tex.png\nmap_Bump -bm 0.5 normal.jpg\n",
+        )
+        .unwrap();
+        image::RgbImage::new(4, 4)
+            .save(dir.join("in/tex.png"))
+            .unwrap();
+        image::RgbImage::new(4, 4)
+            .save(dir.join("in/normal.jpg"))
+            .unwrap();
+
+        let model = Model::try_new_from_file(source.into(), false, None, 1).unwrap();
+        let options = WriteOptions {
+            texture_encoding: TextureEncoding {
+                format: TextureFormat::Webp,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        ModelReference::from_model(&model, 1)
+            .write_to_folder(&dir.join("out").into(), &options)
+            .unwrap();
+
+        let written = std::fs::read_to_string(dir.join("out/tile.obj")).unwrap();
+        assert_eq!(written, obj);
+        let mtl = std::fs::read_to_string(dir.join("out/tile.mtl")).unwrap();
+        assert_eq!(
+            mtl,
+            "# red\nnewmtl red\nmap_Kd tex.webp\nmap_Bump -bm 0.5 normal.webp\n"
+        );
+        assert!(dir.join("out/tex.webp").exists());
+        assert!(dir.join("out/normal.webp").exists());
+        assert!(!dir.join("out/tex.png").exists());
+    }
+
+    #[test]
+    fn test_plan_textures() {
+        let asset = |source_file: &str, out_path: &str, textures: &[&str]| {
+            let materials = textures
+                .iter()
+                .map(|texture| tobj::Material {
+                    diffuse_texture: Some(texture.to_string()),
+                    ..Default::default()
+                })
+                .collect();
+            OutAsset::AssetRef(ModelReference {
+                source_file: source_file.into(),
+                materials,
+                texture_downscale_factor: 1,
+                embedded_textures: HashSet::new(),
+                out_path: out_path.into(),
+                texture_plan: TexturePlan::default(),
+            })
+        };
+        let plan = |out_asset: &OutAsset| match out_asset {
+            OutAsset::AssetRef(model_ref) => model_ref.texture_plan.clone(),
+            OutAsset::Asset(model) => model.texture_plan.clone(),
+        };
+
+        // b shares the textures of a, d has a different texture of the same
+        // name and c is in another folder
+        let mut out_assets = vec![
+            asset("x/c.obj", "sub/c.obj", &["tex.png"]),
+            asset("y/d.obj", "d.obj", &["tex.png", "-bm 0.5 tex.png"]),
+            asset("x/b.obj", "b.obj", &["shared.png", "-bm 0.5 shared.png"]),
+            asset("x/a.obj", "a.obj", &["shared.png", "tex.png"]),
+        ];
+        plan_textures(&mut out_assets, TextureFormat::Keep);
+
+        let plans = out_assets.iter().map(plan).collect::<Vec<_>>();
+        assert!(plans[0].renames.is_empty() && plans[0].shared.is_empty());
+        assert!(plans[1].renames.is_empty());
+        assert_eq!(plans[1].shared, HashSet::from(["shared.png".to_string()]));
+        assert_eq!(plans[2].renames["tex.png"], "tex_1.png");
+        assert!(plans[2].shared.is_empty());
+        assert!(plans[3].renames.is_empty() && plans[3].shared.is_empty());
+
+        let OutAsset::AssetRef(d) = &out_assets[2] else {
+            unreachable!()
+        };
+        assert_eq!(d.source_file, "y/d.obj");
+        let renamed = rename_textures(&d.materials[1], &d.texture_plan, TextureFormat::Keep);
+        assert_eq!(
+            renamed.diffuse_texture.as_deref(),
+            Some("-bm 0.5 tex_1.png")
+        );
+    }
+
+    #[test]
+    fn test_transcode_textures() {
+        let dir = TempDir::new("transcode");
+        image::RgbaImage::new(4, 4).save(dir.join("a.png")).unwrap();
+        image::RgbImage::new(4, 4).save(dir.join("a.jpg")).unwrap();
+
+        let material = |texture: &str| tobj::Material {
+            diffuse_texture: Some(texture.to_string()),
+            ..Default::default()
+        };
+        let mut plan = TexturePlan::default();
+        plan.renames
+            .insert("a.jpg".to_string(), "a_1.jpg".to_string());
+
+        // The sources would both be written as a.webp
+        let renamed = rename_textures(&material("-bm 0.5 a.png"), &plan, TextureFormat::Webp);
+        assert_eq!(renamed.diffuse_texture.as_deref(), Some("-bm 0.5 a.webp"));
+        let renamed = rename_textures(&material("a.jpg"), &plan, TextureFormat::Webp);
+        assert_eq!(renamed.diffuse_texture.as_deref(), Some("a_1.webp"));
+
+        let out = dir.join("out");
+        for (texture, format, factor) in [
+            ("a.png", TextureFormat::Webp, 1),
+            ("a.jpg", TextureFormat::Webp, 2),
+            ("a.png", TextureFormat::Jpeg, 2),
+        ] {
+            let encoding = TextureEncoding {
+                format,
+                jpeg_quality: 50,
+            };
+            copy_texture(
+                texture,
+                &dir,
+                &out,
+                &HashMap::new(),
+                factor,
+                &plan,
+                encoding,
+            )
+            .unwrap();
+        }
+
+        let read = |file: &str| {
+            let reader = ImageReader::open(out.join(file))
+                .unwrap()
+                .with_guessed_format()
+                .unwrap();
+            let format = reader.format();
+            (format, reader.decode().unwrap())
+        };
+        let (format, img) = read("a.webp");
+        assert_eq!(format, Some(ImageFormat::WebP));
+        assert_eq!(img.width(), 4);
+        assert!(img.color().has_alpha());
+        let (format, img) = read("a_1.webp");
+        assert_eq!(format, Some(ImageFormat::WebP));
+        assert_eq!(img.width(), 2);
+        let (format, img) = read("a.jpg");
+        assert_eq!(format, Some(ImageFormat::Jpeg));
+        assert_eq!(img.width(), 2);
+    }
+
+    #[test]
+    fn test_crop_textures() {
+        let dir = TempDir::new("crop");
+        image::RgbImage::new(16, 16)
+            .save(dir.join("tex.png"))
+            .unwrap();
+        image::RgbImage::new(32, 32)
+            .save(dir.join("n.png"))
+            .unwrap();
+
+        let trimesh = three_d_asset::TriMesh {
+            positions: three_d_asset::Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: three_d_asset::Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: Some(vec![
+                Vec2::new(0.25, 0.25),
+                Vec2::new(0.5, 0.25),
+                Vec2::new(0.5, 0.5),
+            ]),
+            colors: None,
+        };
+        let material = tobj::Material {
+            diffuse_texture: Some("tex.png".to_string()),
+            normal_texture: Some("-bm 0.5 n.png".to_string()),
+            ..Default::default()
+        };
+        let mut model = Model {
+            meshes: vec![MeshContainer::new(trimesh, material, false, None).unwrap()],
+            aabb: three_d_asset::AxisAlignedBoundingBox::EMPTY,
+            source_file: dir.join("test.obj").into_os_string(),
+            texture_downscale_factor: 1,
+            embedded_textures: HashMap::new(),
+            overlapping_assets: Default::default(),
+            threshold_range: None,
+            load_time: Default::default(),
+            out_path: "test.obj".into(),
+            texture_plan: Default::default(),
+        };
+        crop_textures(&mut model).unwrap();
+
+        // Pixels 4-8 of tex.png with the margin, rows counted from the top
+        let crops = &model.texture_plan.crops;
+        let crop = |x, y, width, height| TextureCrop {
+            width,
+            height,
+            charts: vec![CropChart {
+                x,
+                y,
+                width,
+                height,
+                dest_x: 0,
+                dest_y: 0,
+            }],
+        };
+        assert_eq!(crops["tex.png"], crop(2, 6, 8, 8));
+        assert_eq!(crops["n.png"], crop(4, 12, 16, 16));
+        let uvs = model.meshes[0].mesh.uvs.as_ref().unwrap();
+        assert_eq!(uvs[0], Vec2::new(0.25, 0.25));
+        assert_eq!(uvs[2], Vec2::new(0.75, 0.75));
+
+        let out = dir.join("out");
+        copy_texture(
+            "-bm 0.5 n.png",
+            &dir,
+            &out,
+            &HashMap::new(),
+            1,
+            &model.texture_plan,
+            TextureEncoding::default(),
+        )
+        .unwrap();
+        let size = image::image_dimensions(out.join("n.png")).unwrap();
+        assert_eq!(size, (16, 16));
+    }
+
+    #[test]
+    fn test_crop_texture_charts() {
+        let dir = TempDir::new("crop_charts");
+        // Red where the second island is
+        let mut tex = image::RgbImage::new(64, 64);
+        for y in 2..6 {
+            for x in 58..62 {
+                tex.put_pixel(x, y, image::Rgb([255, 0, 0]));
+            }
+        }
+        tex.save(dir.join("tex.png")).unwrap();
+
+        // Two uv islands in opposite corners of the texture
+        let trimesh = three_d_asset::TriMesh {
+            positions: three_d_asset::Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(2.0, 1.0, 0.0),
+            ]),
+            indices: three_d_asset::Indices::U32(vec![0, 1, 2, 3, 4, 5]),
+            normals: None,
+            tangents: None,
+            uvs: Some(vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(0.0625, 0.0),
+                Vec2::new(0.0, 0.0625),
+                Vec2::new(59.0 / 64.0, 59.0 / 64.0),
+                Vec2::new(61.0 / 64.0, 59.0 / 64.0),
+                Vec2::new(59.0 / 64.0, 61.0 / 64.0),
+            ]),
+            colors: None,
+        };
+        let material = tobj::Material {
+            diffuse_texture: Some("tex.png".to_string()),
+            ..Default::default()
+        };
+        let mut model = Model {
+            meshes: vec![MeshContainer::new(trimesh, material, false, None).unwrap()],
+            aabb: three_d_asset::AxisAlignedBoundingBox::EMPTY,
+            source_file: dir.join("test.obj").into_os_string(),
+            texture_downscale_factor: 1,
+            embedded_textures: HashMap::new(),
+            overlapping_assets: Default::default(),
+            threshold_range: None,
+            load_time: Default::default(),
+            out_path: "test.obj".into(),
+            texture_plan: Default::default(),
+        };
+        crop_textures(&mut model).unwrap();
+
+        // The bounding box of both islands is the whole texture
+        let crop = &model.texture_plan.crops["tex.png"];
+        assert_eq!(crop.charts.len(), 2);
+        assert!(crop.width * crop.height * 8 < 64 * 64);
+
+        let out = dir.join("out");
+        copy_texture(
+            "tex.png",
+            &dir,
+            &out,
+            &HashMap::new(),
+            1,
+            &model.texture_plan,
+            TextureEncoding::default(),
+        )
+        .unwrap();
+        let cropped = image::open(out.join("tex.png")).unwrap().to_rgb8();
+        assert_eq!(cropped.dimensions(), (crop.width, crop.height));
+
+        // The uvs of the second island still sample the red pixels
+        let uvs = model.meshes[0].mesh.uvs.as_ref().unwrap();
+        for uv in &uvs[3..] {
+            let x = (uv.x * crop.width as f32) as u32;
+            let y = ((1.0 - uv.y) * crop.height as f32) as u32;
+            assert_eq!(cropped.get_pixel(x, y).0, [255, 0, 0]);
+        }
+        for uv in &uvs[..3] {
+            assert!((0.0..=1.0).contains(&uv.x) && (0.0..=1.0).contains(&uv.y));
+        }
+    }
+
+    #[test]
+    fn test_split_texture_options() {
+        assert_eq!(split_texture_options("stone.png"), ("", "stone.png"));
+        assert_eq!(
+            split_texture_options("-s 2 2 1 -clamp on stone.png"),
+            ("-s 2 2 1 -clamp on", "stone.png")
+        );
+        assert_eq!(
+            split_texture_options("-o 0.5 my stone.png"),
+            ("-o 0.5", "my stone.png")
+        );
+        assert_eq!(
+            split_texture_options("-mm 0 1 -bm 0.2 n.png"),
+            ("-mm 0 1 -bm 0.2", "n.png")
+        );
+        assert_eq!(split_texture_options("-weird.png"), ("", "-weird.png"));
+    }
+
+    #[test]
+    fn test_parse_capture_date() {
+        let date = CaptureDate::parse("2023-05-01").unwrap();
+        let time = CaptureDate::parse("2023-05-01T14:30:00Z").unwrap();
+        assert_eq!(CaptureDate::parse("2023-05-01T00:00:00"), Some(date));
+        assert!(date < time);
+        assert!(time < CaptureDate::parse("2023-05-02").unwrap());
+        assert!(
+            CaptureDate::parse("2023-12-31").unwrap() < CaptureDate::parse("2024-01-01").unwrap()
+        );
+
+        for invalid in [
+            "2023-5-1",
+            "2023-13-01",
+            "2023-05-00",
+            "23-05-01",
+            "2023/05/01",
+            "2023-05-01T25:00:00",
+            "2023-05-01T14:30",
+            "May 1, 2023",
+            "",
+        ] {
+            assert_eq!(CaptureDate::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_obj_faces_match_mesh_attributes() {
+        let mesh = |uvs: bool, normals: bool, object: Option<&str>, group: Option<&str>| {
+            let trimesh = three_d_asset::TriMesh {
+                positions: three_d_asset::Positions::F32(vec![
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(1.0, 0.0, 0.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                ]),
+                indices: three_d_asset::Indices::U32(vec![0, 1, 2]),
+                normals: normals.then(|| vec![Vec3::new(0.0, 0.0, 1.0); 3]),
+                tangents: None,
+                uvs: uvs.then(|| vec![Vec2::new(0.0, 0.0); 3]),
+                colors: None,
+            };
+            let mut container =
+                MeshContainer::new(trimesh, tobj::Material::default(), false, None).unwrap();
+            container.names = MeshNames {
+                object: object.map(str::to_string),
+                group: group.map(str::to_string),
+            };
+            container
+        };
+        let model = Model {
+            meshes: vec![
+                mesh(false, true, Some("tile"), Some("roof")),
+                mesh(true, true, Some("tile"), Some("walls")),
+                mesh(false, false, Some("tile"), None),
+                mesh(true, false, None, None),
+            ],
+            aabb: three_d_asset::AxisAlignedBoundingBox::EMPTY,
+            source_file: "test.obj".into(),
+            texture_downscale_factor: 1,
+            embedded_textures: HashMap::new(),
+            overlapping_assets: Default::default(),
+            threshold_range: None,
+            load_time: Default::default(),
+            out_path: "test.obj".into(),
+            texture_plan: Default::default(),
+        };
+
+        let mut written = Vec::new();
+        write_obj(&mut written, &model, Path::new("test.mtl")).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let groups = written
+            .lines()
+            .filter(|l| l.starts_with("o ") || l.starts_with("g "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            ["o tile", "g roof", "g walls", "o tile", "g default"]
+        );
+
+        let faces = written
+            .lines()
+            .filter(|l| l.starts_with("f "))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            faces,
+            [
+                "f 1//1 2//2 3//3",
+                "f 4/1/4 5/2/5 6/3/6",
+                "f 7 8 9",
+                "f 10/4 11/5 12/6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_materials_round_trip() {
+        let mtl = "newmtl stone\n\
+                   Ka 0.1 0.1 0.1\nKd 0.5 0.4 0.3\nKs 0.2 0.2 0.2\n\
+                   Ns 10\nNi 1.5\nd 0.9\nillum 2\n\
+                   map_Kd -s 2 2 1 -o 0.5 0 0 stone.png\nmap_Ks spec.png\n\
+                   map_Ka amb.png\nmap_Ns shine.png\nmap_d alpha.png\n\
+                   map_Bump -bm 0.5 normal.png\nmap_Ke glow.png\nPr 0.7\n";
+        let (materials, _) = tobj::load_mtl_buf(&mut mtl.as_bytes()).unwrap();
+
+        let mut written = Vec::new();
+        write_materials(&mut written, &materials.iter().collect::<Vec<_>>()).unwrap();
+        let (round_trip, _) = tobj::load_mtl_buf(&mut written.as_slice()).unwrap();
+
+        let (a, b) = (&materials[0], &round_trip[0]);
+        assert_eq!(round_trip.len(), 1);
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.ambient, b.ambient);
+        assert_eq!(a.diffuse, b.diffuse);
+        assert_eq!(a.specular, b.specular);
+        assert_eq!(a.shininess, b.shininess);
+        assert_eq!(a.optical_density, b.optical_density);
+        assert_eq!(a.dissolve, b.dissolve);
+        assert_eq!(a.illumination_model, b.illumination_model);
+        assert_eq!(material_textures(a), material_textures(b));
+        assert_eq!(a.unknown_param, b.unknown_param);
+        assert_eq!(
+            material_textures(&round_trip[0]),
+            [
+                "amb.png",
+                "-s 2 2 1 -o 0.5 0 0 stone.png",
+                "spec.png",
+                "-bm 0.5 normal.png",
+                "shine.png",
+                "alpha.png",
+                "glow.png"
+            ]
+        );
+    }
 }
@@ -0,0 +1,213 @@
+//! Removes geometry from normal assets where it overlaps with hq assets.
+//! Assets are read from OBJ, glTF and GLB files and written as OBJ or GLB.
+//! More than two tiers can be given as [`AssetLayer`]s, each cleaned against
+//! all layers above it.
+//!
+//! The [`clean`] function runs the whole pipeline. The building blocks
+//! ([`WorldAssets`], [`Model`], [`MeshContainer`], [`IndexGrid`] and
+//! [`WriteToFolder`]) are exposed for callers that need finer control.
+
+use std::{path::Path, time::Instant};
+
+mod config;
+mod error;
+mod gltf_io;
+mod grid;
+mod io;
+mod messages;
+mod model;
+mod report;
+#[cfg(test)]
+mod test_utils;
+mod world;
+
+pub use config::{AssetLayer, Config};
+pub use error::{AssetFailure, CleanerError};
+pub use grid::{GridCellMode, GridCellSize, GridStats, IndexGrid};
+pub use io::{
+    CollisionMode, CropChart, OutputFormat, TextureCrop, TextureEncoding, TextureFormat,
+    TexturePlan, WriteOptions, WriteToFolder,
+};
+pub use model::{
+    AssetFormat, BoundaryCut, CutMode, DistanceTest, IslandMode, IslandThreshold, MeshContainer,
+    MeshNames, Model, ModelReference, NormalMode, OutAsset, OverlapThreshold, ThresholdMode,
+    VertexClass,
+};
+pub use report::{AssetOutcome, AssetRole, AssetSummary, Report};
+pub use world::{HqTieBreak, WorldAssets};
+
+/// Name of the JSON report written to the out folder
+pub const REPORT_FILE_NAME: &str = "report.json";
+
+/// Runs the overlap removal for `config` and writes the results to
+/// `config.out_folder`, together with the report as `report.json`. With
+/// [`Config::dry_run`] nothing is written, and the removals are only
+/// described by [`Report::asset_summaries`].
+///
+/// Errors are only returned for failures that prevent the whole run, such as
+/// unreadable input folders. Failures of individual assets are collected to
+/// [`Report::failed_assets`].
+pub fn clean(config: &Config) -> Result<Report, CleanerError> {
+    let start_time = Instant::now();
+
+    // Create out-folder if it doesn't exist
+    if !config.dry_run {
+        std::fs::create_dir_all(&config.out_folder)
+            .map_err(|e| CleanerError::io(&config.out_folder, e))?;
+    }
+
+    if let Some(debug_folder) = &config.debug_folder {
+        std::fs::create_dir_all(debug_folder).map_err(|e| CleanerError::io(debug_folder, e))?;
+    }
+
+    let mut assets = WorldAssets::new(config)?;
+
+    println!("Finding non-overlapping models");
+    loop {
+        assets.process_overlaps();
+        assets.mark_and_delete_vertices();
+        if !assets.next_layer() {
+            break;
+        }
+    }
+    if !config.dry_run {
+        assets.write_to_folder(&config.out_folder);
+    }
+
+    let mut report = assets.into_report();
+    report.duration = Instant::now() - start_time;
+
+    if !config.dry_run {
+        report.write_json(
+            &Path::new(&config.out_folder).join(REPORT_FILE_NAME),
+            config,
+        )?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{OverlapThreshold, ThresholdMode},
+        test_utils::{TempDir, test_config, write_obj},
+    };
+
+    /// Two triangles on z = 0 covering the square from 0 to 10
+    const HQ_SQUARE: &[[f32; 3]] = &[
+        [0.0, 0.0, 0.0],
+        [10.0, 0.0, 0.0],
+        [10.0, 10.0, 0.0],
+        [0.0, 0.0, 0.0],
+        [10.0, 10.0, 0.0],
+        [0.0, 10.0, 0.0],
+    ];
+    /// Triangle just above the hq square
+    const COVERED: &[[f32; 3]] = &[[1.0, 1.0, 0.05], [2.0, 1.0, 0.05], [2.0, 2.0, 0.05]];
+    /// Triangle well away from the hq square
+    const OUTSIDE: &[[f32; 3]] = &[[20.0, 1.0, 0.0], [21.0, 1.0, 0.0], [21.0, 2.0, 0.0]];
+
+    #[test]
+    fn test_dry_run_lists_dropped_meshes() {
+        let dir = TempDir::new("dry_run");
+        write_obj(&dir.join("hq/hq.obj"), &[("hq", HQ_SQUARE)]);
+        write_obj(
+            &dir.join("normal/tile.obj"),
+            &[("covered", COVERED), ("outside", OUTSIDE)],
+        );
+        let config = Config {
+            dry_run: true,
+            ..test_config(&dir)
+        };
+
+        let report = clean(&config).unwrap();
+
+        assert!(!dir.join("out").exists());
+        let summary = report
+            .asset_summaries
+            .iter()
+            .find(|s| s.path == dir.join("normal/tile.obj").as_os_str())
+            .unwrap();
+        assert_eq!(summary.outcome, AssetOutcome::Rewritten);
+        assert_eq!(summary.dropped_meshes, ["covered"]);
+        assert_eq!(summary.triangles_after, 1);
+    }
+
+    #[test]
+    fn test_fully_overlapping_asset_is_deleted() {
+        let dir = TempDir::new("deleted_asset");
+        write_obj(&dir.join("hq/hq.obj"), &[("hq", HQ_SQUARE)]);
+        write_obj(&dir.join("normal/covered.obj"), &[("covered", COVERED)]);
+        write_obj(&dir.join("normal/outside.obj"), &[("outside", OUTSIDE)]);
+
+        let report = clean(&test_config(&dir)).unwrap();
+
+        let covered = dir.join("normal/covered.obj").into_os_string();
+        assert_eq!(report.deleted_assets.len(), 1);
+        assert_eq!(report.deleted_assets[0], covered);
+        let summary = report
+            .asset_summaries
+            .iter()
+            .find(|s| s.path == covered)
+            .unwrap();
+        assert_eq!(summary.outcome, AssetOutcome::Deleted);
+        assert_eq!(summary.deleted_triangles(), 1);
+        assert!(!dir.join("out/covered.obj").exists());
+        assert!(dir.join("out/outside.obj").exists());
+    }
+
+    #[test]
+    fn test_layers_cleaned_against_cleaned_layers() {
+        let dir = TempDir::new("three_layers");
+        let shift = |corners: &[[f32; 3]], dz: f32| {
+            corners
+                .iter()
+                .map(|[x, y, z]| [*x, *y, z + dz])
+                .collect::<Vec<_>>()
+        };
+        write_obj(&dir.join("l0/a.obj"), &[("a", HQ_SQUARE)]);
+        write_obj(
+            &dir.join("l1/b.obj"),
+            &[("b_over", COVERED), ("b_out", OUTSIDE)],
+        );
+        // Both within the threshold of b, but not of a
+        write_obj(
+            &dir.join("l2/c.obj"),
+            &[
+                ("c_over", &shift(COVERED, 0.07)),
+                ("c_out", &shift(OUTSIDE, 0.07)),
+            ],
+        );
+        let layer = |folder: &str| AssetLayer {
+            folders: vec![dir.join(folder).into()],
+            overlap_threshold: OverlapThreshold {
+                mode: ThresholdMode::Absolute,
+                value: 0.1,
+            },
+            texture_downscale: 1,
+        };
+        let config = Config {
+            layers: vec![layer("l0"), layer("l1"), layer("l2")],
+            dry_run: true,
+            ..test_config(&dir)
+        };
+
+        let report = clean(&config).unwrap();
+
+        let summary = |file: &str| {
+            report
+                .asset_summaries
+                .iter()
+                .find(|s| s.path == dir.join(file).as_os_str())
+                .unwrap()
+        };
+        assert_eq!(summary("l1/b.obj").dropped_meshes, ["b_over"]);
+        // b_over was removed, so c_over is no longer covered
+        let c = summary("l2/c.obj");
+        assert_eq!(c.outcome, AssetOutcome::Rewritten);
+        assert_eq!(c.dropped_meshes, ["c_out"]);
+        assert_eq!(c.layer, 2);
+    }
+}
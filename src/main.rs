@@ -1,48 +1,345 @@
 use clap::Parser;
-use std::{ffi::OsString, path::PathBuf, time::Instant};
+use std::ffi::OsString;
 
-mod grid;
-mod io;
-mod messages;
-mod model;
-mod world;
-
-use model::Model;
+use obj_overlap_cleaner::{
+    AssetLayer, CollisionMode, Config, CutMode, DistanceTest, GridCellMode, GridCellSize,
+    HqTieBreak, IslandMode, IslandThreshold, NormalMode, OutputFormat, OverlapThreshold, Report,
+    TextureFormat, ThresholdMode,
+};
 
 #[derive(Debug, Parser)]
 struct Args {
     /// Space separated list of folders containing hq assets
-    #[clap(long, value_parser, num_args = 1.., value_delimiter = ' ')]
+    #[clap(long, value_parser, num_args = 1.., value_delimiter = ' ', conflicts_with = "layer")]
     hq_asset_folders: Vec<OsString>,
 
     /// Folder containing the normal assets
-    #[clap(long)]
-    normal_asset_folder: OsString,
+    #[clap(long, required_unless_present = "layer", conflicts_with = "layer")]
+    normal_asset_folder: Option<OsString>,
+
+    /// Asset layer, repeated from the highest priority to the lowest instead
+    /// of --hq-asset-folders and --normal-asset-folder. Each layer is
+    /// cleaned against all layers above it. Given as comma separated
+    /// folders, optionally followed by ";threshold=VALUE" overriding
+    /// --overlap-threshold and ";texture-scale=FACTOR" overriding
+    /// --hq-texture-scale for the first layer and --normal-texture-scale for
+    /// the others.
+    #[clap(long, value_parser = parse_layer)]
+    layer: Vec<LayerArg>,
+
+    /// Also clean the hq assets (the first layer) against each other. Of two
+    /// overlapping hq assets, the one winning this tie-break keeps the
+    /// overlapping geometry.
+    #[clap(long, value_enum)]
+    clean_hq: Option<HqTieBreak>,
 
     out_folder: OsString,
+
+    /// Glob of the assets to use from the input folders, can be repeated.
+    /// Globs without a '/' are matched against file names, others against
+    /// the path relative to the input folder. All assets are used when none
+    /// are given.
+    #[clap(long)]
+    include: Vec<String>,
+
+    /// Glob of the assets and subfolders to skip in the input folders, can
+    /// be repeated
+    #[clap(long)]
+    exclude: Vec<String>,
+
+    /// How assets that would be written to the same output path, e.g.
+    /// tile.obj in two input folders, are told apart
+    #[clap(long, value_enum, default_value_t = CollisionMode::Rename)]
+    collision_mode: CollisionMode,
+
+    /// How overlapping geometry is removed from the normal assets
+    #[clap(long, value_enum, default_value_t = CutMode::Vertices)]
+    cut_mode: CutMode,
+
+    /// Close the gaps between the cleaned normal assets and the hq assets
+    /// with new triangles
+    #[clap(long)]
+    stitch_seams: bool,
+
+    /// How the normals of the rewritten assets are produced
+    #[clap(long, value_enum, default_value_t = NormalMode::Keep)]
+    normal_mode: NormalMode,
+
+    /// What the overlap threshold is relative to
+    #[clap(long, value_enum, default_value_t = ThresholdMode::MeanEdge)]
+    threshold_mode: ThresholdMode,
+
+    /// Overlap threshold: a distance in model units, or a multiplier of the
+    /// mean edge length, depending on --threshold-mode
+    #[clap(long, default_value_t = 10.0)]
+    overlap_threshold: f32,
+
+    /// How the distance between normal asset vertices and hq triangles is
+    /// measured
+    #[clap(long, value_enum, default_value_t = DistanceTest::Exact)]
+    distance_test: DistanceTest,
+
+    /// How the size of the islands left between overlapping vertices is
+    /// measured
+    #[clap(long, value_enum, default_value_t = IslandMode::VertexCount)]
+    island_mode: IslandMode,
+
+    /// Islands smaller than this are removed: a vertex count, an area in
+    /// square model units or a bounding box diagonal in model units,
+    /// depending on --island-mode. Defaults to 15, 1 and 1 respectively.
+    #[clap(long)]
+    island_threshold: Option<f32>,
+
+    /// How the cell size of the hq asset index grids is chosen
+    #[clap(long, value_enum, default_value_t = GridCellMode::MeanEdge)]
+    grid_cell_mode: GridCellMode,
+
+    /// Grid cell size: a multiplier of the mean edge length, the number of
+    /// cells along the longest bounding box side, or a size in model units,
+    /// depending on --grid-cell-mode. Defaults to 4, 100 and 0.1 respectively.
+    #[clap(long)]
+    grid_cell_size: Option<f32>,
+
+    /// File format of the written assets
+    #[clap(long, value_enum, default_value_t = OutputFormat::Obj)]
+    output_format: OutputFormat,
+
+    /// Store the textures inside the GLB files instead of next to them
+    #[clap(long)]
+    embed_textures: bool,
+
+    /// Factor the textures of the hq assets are downscaled by, or "copy" to
+    /// copy them as they are
+    #[clap(long, value_parser = parse_downscale, default_value = "copy")]
+    hq_texture_scale: u32,
+
+    /// Factor the textures of the normal assets are downscaled by, or "copy"
+    /// to copy them as they are
+    #[clap(long, value_parser = parse_downscale, default_value = "2")]
+    normal_texture_scale: u32,
+
+    /// Texture downscale factor or "copy" for the assets in a folder, as
+    /// FOLDER=FACTOR. Overrides --hq-texture-scale and --normal-texture-scale
+    /// and can be repeated.
+    #[clap(long, value_parser = parse_folder_downscale)]
+    folder_texture_scale: Vec<(OsString, u32)>,
+
+    /// File format the textures are written as. Texture references in the
+    /// MTL files are renamed to match.
+    #[clap(long, value_enum, default_value_t = TextureFormat::Keep)]
+    texture_format: TextureFormat,
+
+    /// Quality of the textures written as JPEG, 1-100
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=100), default_value_t = 90)]
+    jpeg_quality: u8,
+
+    /// Crop the textures of the assets that lost geometry to the uv charts
+    /// the remaining triangles use, packed to smaller textures, remapping
+    /// their uvs
+    #[clap(long)]
+    crop_textures: bool,
+
+    /// Print what would be removed from each normal asset without writing
+    /// anything
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Write each normal asset to this folder as OBJ with vertex colours
+    /// showing which vertices were kept, overlapping, deleted, removed as
+    /// islands or kept on the boundary
+    #[clap(long)]
+    debug_folder: Option<OsString>,
+}
+
+/// Asset layer given with --layer, the options left out use the global ones
+#[derive(Debug, Clone)]
+struct LayerArg {
+    folders: Vec<OsString>,
+    overlap_threshold: Option<f32>,
+    texture_scale: Option<u32>,
 }
 
-fn main() {
+impl From<Args> for Config {
+    fn from(args: Args) -> Self {
+        let layers = args
+            .layer
+            .iter()
+            .enumerate()
+            .map(|(idx, layer)| AssetLayer {
+                folders: layer.folders.clone(),
+                overlap_threshold: OverlapThreshold {
+                    mode: args.threshold_mode,
+                    value: layer.overlap_threshold.unwrap_or(args.overlap_threshold),
+                },
+                texture_downscale: layer.texture_scale.unwrap_or(match idx {
+                    0 => args.hq_texture_scale,
+                    _ => args.normal_texture_scale,
+                }),
+            })
+            .collect();
+
+        let mut config = Config::default();
+        config.hq_asset_folders = args.hq_asset_folders;
+        config.normal_asset_folder = args.normal_asset_folder.unwrap_or_default();
+        config.layers = layers;
+        config.hq_tie_break = args.clean_hq;
+        config.out_folder = args.out_folder;
+        config.include = args.include;
+        config.exclude = args.exclude;
+        config.collision_mode = args.collision_mode;
+        config.cut_mode = args.cut_mode;
+        config.stitch_seams = args.stitch_seams;
+        config.normal_mode = args.normal_mode;
+        config.overlap_threshold = OverlapThreshold {
+            mode: args.threshold_mode,
+            value: args.overlap_threshold,
+        };
+        config.distance_test = args.distance_test;
+        config.island_threshold = IslandThreshold {
+            mode: args.island_mode,
+            value: args
+                .island_threshold
+                .unwrap_or_else(|| args.island_mode.default_value()),
+        };
+        config.grid_cell_size = GridCellSize {
+            mode: args.grid_cell_mode,
+            value: args
+                .grid_cell_size
+                .unwrap_or_else(|| args.grid_cell_mode.default_value()),
+        };
+        config.output_format = args.output_format;
+        config.embed_textures = args.embed_textures;
+        config.hq_texture_downscale = args.hq_texture_scale;
+        config.normal_texture_downscale = args.normal_texture_scale;
+        config.texture_downscale_overrides = args.folder_texture_scale;
+        config.texture_format = args.texture_format;
+        config.jpeg_quality = args.jpeg_quality;
+        config.crop_textures = args.crop_textures;
+        config.dry_run = args.dry_run;
+        config.debug_folder = args.debug_folder;
+        config
+    }
+}
+
+fn parse_downscale(value: &str) -> Result<u32, String> {
+    match value {
+        "copy" => Ok(1),
+        _ => match value.parse::<u32>() {
+            Ok(factor) if factor >= 1 => Ok(factor),
+            _ => Err(format!(
+                "expected \"copy\" or a positive integer, got {value:?}"
+            )),
+        },
+    }
+}
+
+fn parse_folder_downscale(value: &str) -> Result<(OsString, u32), String> {
+    let (folder, factor) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected FOLDER=FACTOR, got {value:?}"))?;
+    Ok((folder.into(), parse_downscale(factor)?))
+}
+
+fn parse_layer(value: &str) -> Result<LayerArg, String> {
+    let mut parts = value.split(';');
+    let folders = parts
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|folder| !folder.is_empty())
+        .map(OsString::from)
+        .collect::<Vec<_>>();
+    if folders.is_empty() {
+        return Err(format!("expected at least one folder, got {value:?}"));
+    }
+
+    let mut layer = LayerArg {
+        folders,
+        overlap_threshold: None,
+        texture_scale: None,
+    };
+    for option in parts {
+        match option.split_once('=') {
+            Some(("threshold", threshold)) => {
+                layer.overlap_threshold = Some(
+                    threshold
+                        .parse::<f32>()
+                        .map_err(|_| format!("expected a number, got {threshold:?}"))?,
+                );
+            }
+            Some(("texture-scale", factor)) => layer.texture_scale = Some(parse_downscale(factor)?),
+            _ => {
+                return Err(format!(
+                    "expected threshold=VALUE or texture-scale=FACTOR, got {option:?}"
+                ));
+            }
+        }
+    }
+    Ok(layer)
+}
+
+fn print_dry_run(report: &Report) {
+    for summary in report.asset_summaries.iter() {
+        println!(
+            "{:?}: would delete {} of {} vertices and {} of {} triangles",
+            summary.path,
+            summary.deleted_vertices(),
+            summary.vertices_before,
+            summary.deleted_triangles(),
+            summary.triangles_before
+        );
+        if !summary.dropped_meshes.is_empty() {
+            println!("  dropped meshes: {}", summary.dropped_meshes.join(", "));
+        }
+        for hq_asset in summary.overlapping_assets.iter() {
+            println!("  overlaps with {hq_asset:?}");
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let start_time = Instant::now();
 
     println!("Running with args: {args:?}");
 
-    // Create out-folder if it doesn't exist
-    let out_path = PathBuf::from(&args.out_folder);
-    std::fs::create_dir_all(out_path)
-        .unwrap_or_else(|_| panic!("Couldn't create output directory: {:?}", args.out_folder));
+    let config: Config = args.into();
+    let report = obj_overlap_cleaner::clean(&config)?;
+
+    if config.dry_run {
+        print_dry_run(&report);
+    }
+
+    println!(
+        "Copied {} and rewrote {} normal assets, {} were fully overlapping",
+        report.copied_assets.len(),
+        report.rewritten_assets.len(),
+        report.deleted_assets.len()
+    );
+    println!("Done in {} s", report.duration.as_secs());
+
+    if !report.failed_assets.is_empty() {
+        println!("Failed assets:");
+        for failure in report.failed_assets.iter() {
+            println!("  {:?}: {}", failure.path, failure.error);
+        }
+        anyhow::bail!("{} assets failed", report.failed_assets.len());
+    }
+
+    Ok(())
+}
 
-    let mut assets =
-        world::WorldAssets::new(args.normal_asset_folder, args.hq_asset_folders.clone());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Finding non-overlapping models");
-    assets.process_overlaps();
-    //assets.mark_vertices_to_delete();
-    assets.mark_and_delete_vertices();
-    //assets.do_delete_vertices();
-    assets.write_to_folder(&args.out_folder);
+    #[test]
+    fn test_config_default_matches_args() {
+        let args = Args::parse_from(["cleaner", "--normal-asset-folder", "normal", "out"]);
+        let config = Config::from(args);
 
-    let duration = (Instant::now() - start_time).as_secs();
-    println!("Done in {duration} s");
+        let mut expected = Config::default();
+        expected.normal_asset_folder = "normal".into();
+        expected.out_folder = "out".into();
+        assert_eq!(format!("{config:?}"), format!("{expected:?}"));
+    }
 }
@@ -1,7 +1,12 @@
 use std::ffi::OsString;
 
+use crate::{error::CleanerError, grid::GridCellSize};
+
 pub struct TaskContainer {
     pub path: OsString,
+    pub texture_downscale_factor: u32,
+    /// Cell size of the index grids, for models loaded as hq assets
+    pub index_grid_cell_size: Option<GridCellSize>,
 }
 
 pub enum ModelLoadTask {
@@ -10,10 +15,16 @@ pub enum ModelLoadTask {
 }
 
 pub struct ModelContainer {
-    pub model: crate::Model,
+    pub model: Box<crate::Model>,
+}
+
+pub struct FailedContainer {
+    pub path: OsString,
+    pub error: CleanerError,
 }
 
 pub enum ModelLoadTaskResponse {
     Terminated,
     Model(ModelContainer),
+    Failed(FailedContainer),
 }
@@ -1,6 +1,10 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     ffi::{OsStr, OsString},
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use three_d_asset::{
@@ -9,10 +13,126 @@ use three_d_asset::{
 };
 use tobj::{Material as TobjMaterial, Mesh as TobjMesh};
 
-use crate::grid::IndexGrid;
+use crate::{
+    error::CleanerError,
+    gltf_io,
+    grid::{GridCellSize, IndexGrid},
+    io::TexturePlan,
+};
 
 const EPSILON: f64 = 1e-10;
 
+/// Number of bisection steps used to locate the boundary along an edge
+const BOUNDARY_SEARCH_STEPS: u32 = 10;
+
+/// How overlapping geometry is removed from normal assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CutMode {
+    /// Delete overlapping vertices, keeping those on the boundary. The cut
+    /// follows the triangle edges of the normal asset.
+    #[default]
+    Vertices,
+    /// Split the boundary triangles where their edges leave the hq asset,
+    /// so the cut follows the outline of the hq asset.
+    Clip,
+}
+
+/// Vertex identified by the bit pattern of its position, so that it can be
+/// matched before and after the vertices have been reindexed
+type VertexKey = [u32; 3];
+
+fn vertex_key(p: Vec3) -> VertexKey {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+/// Returns the directed edges that are used by only one triangle
+fn boundary_edges(indices: &[u32]) -> Vec<(u32, u32)> {
+    let mut edge_cnt: HashMap<(u32, u32), usize> = HashMap::new();
+
+    for tri in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (i0, i1) = (tri[i], tri[(i + 1) % 3]);
+            *edge_cnt.entry((i0.min(i1), i0.max(i1))).or_default() += 1;
+        }
+    }
+
+    let mut edges = vec![];
+    for tri in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (i0, i1) = (tri[i], tri[(i + 1) % 3]);
+            if edge_cnt[&(i0.min(i1), i0.max(i1))] == 1 {
+                edges.push((i0, i1));
+            }
+        }
+    }
+
+    edges
+}
+
+/// Returns aabb grown by margin on every side
+pub(crate) fn expand_aabb(aabb: AxisAlignedBoundingBox, margin: f32) -> AxisAlignedBoundingBox {
+    let margin = Vec3::new(margin, margin, margin);
+    AxisAlignedBoundingBox::new_with_positions(&[aabb.min() - margin, aabb.max() + margin])
+}
+
+/// Closest point to p on the segment
+fn closest_point_on_segment(p: Vec3, segment: &[Vec3; 2]) -> Vec3 {
+    let d = segment[1] - segment[0];
+    let len2 = d.magnitude2();
+    if len2 == 0.0 {
+        return segment[0];
+    }
+    let t = ((p - segment[0]).dot(d) / len2).clamp(0.0, 1.0);
+    segment[0] + d * t
+}
+
+/// Edge of a normal mesh crossing the boundary of an hq mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundaryCut {
+    /// Index of the non-overlapping vertex
+    pub kept: u32,
+    /// Index of the overlapping vertex
+    pub overlapping: u32,
+    /// Position of the boundary along the edge, 0.0 being at kept
+    pub t: f32,
+}
+
+pub(crate) fn positions_f32(mesh: &TriMesh) -> Result<&[Vec3], CleanerError> {
+    match &mesh.positions {
+        Positions::F32(positions) => Ok(positions),
+        _ => Err(CleanerError::UnsupportedMesh("Positions not F32")),
+    }
+}
+
+pub(crate) fn indices_u32(mesh: &TriMesh) -> Result<&[u32], CleanerError> {
+    match &mesh.indices {
+        Indices::U32(indices) => Ok(indices),
+        _ => Err(CleanerError::UnsupportedMesh("Indices not U32")),
+    }
+}
+
+/// Mean length of the triangle edges of mesh. Meshes without triangles don't
+/// have one.
+pub(crate) fn mean_edge_length(mesh: &TriMesh) -> Result<f32, CleanerError> {
+    let mut len_sum = 0.0;
+    let mut len_cnt = 0;
+    let positions = positions_f32(mesh)?;
+    mesh.for_each_triangle(|i0, i1, i2| {
+        let p0 = positions[i0];
+        let p1 = positions[i1];
+        let p2 = positions[i2];
+
+        len_sum += p0.distance(p1);
+        len_sum += p1.distance(p2);
+        len_sum += p2.distance(p0);
+        len_cnt += 3;
+    });
+    if len_cnt == 0 {
+        return Err(CleanerError::EmptyMesh);
+    }
+    Ok(len_sum / len_cnt as f32)
+}
+
 fn tobj_mesh_to_trimesh(mesh: TobjMesh) -> TriMesh {
     let uvs = if !mesh.texcoords.is_empty() {
         Some(
@@ -51,81 +171,456 @@ fn tobj_mesh_to_trimesh(mesh: TobjMesh) -> TriMesh {
     }
 }
 
-fn try_load_and_process_obj(
-    path: &OsStr,
-) -> Result<(Vec<TriMesh>, Vec<TobjMaterial>), tobj::LoadError> {
-    let (models, materials) = tobj::load_obj(
-        path,
+/// Object and group the mesh belongs to in the source file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MeshNames {
+    pub object: Option<String>,
+    pub group: Option<String>,
+}
+
+/// Meshes of a source file with their materials and names
+pub(crate) type LoadedMeshes = Vec<(TriMesh, TobjMaterial, MeshNames)>;
+
+/// Passes an OBJ file through to tobj line by line, replacing the name of
+/// each `o` and `g` statement with the index of the statement. tobj names
+/// its models after the last of these statements, so the name of each model
+/// tells which statement it belongs to, also when names repeat. The object
+/// and group names the statements had are collected to `names`.
+struct ObjNameReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    pos: usize,
+    object: Option<String>,
+    names: Vec<MeshNames>,
+}
+
+impl<R: BufRead> ObjNameReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: vec![],
+            pos: 0,
+            object: None,
+            names: vec![],
+        }
+    }
+
+    /// Names of the statement a model tobj loaded belongs to
+    fn model_names(&self, model_name: &str) -> MeshNames {
+        model_name
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| self.names.get(idx))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn rename_statement(&mut self) {
+        let line = String::from_utf8_lossy(&self.line);
+        let line = line.trim();
+        let (statement, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if statement != "o" && statement != "g" {
+            return;
+        }
+
+        // tobj names statements without a name the same way
+        let name = match name.trim() {
+            "" => "unnamed_object".to_string(),
+            name => name.to_string(),
+        };
+        let names = match statement {
+            "o" => {
+                self.object = Some(name.clone());
+                MeshNames {
+                    object: Some(name),
+                    group: None,
+                }
+            }
+            _ => MeshNames {
+                object: self.object.clone(),
+                group: Some(name),
+            },
+        };
+        self.line = format!("g {}\n", self.names.len()).into_bytes();
+        self.names.push(names);
+    }
+}
+
+impl<R: BufRead> Read for ObjNameReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for ObjNameReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            self.inner.read_until(b'\n', &mut self.line)?;
+            self.rename_statement();
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+}
+
+/// Material for meshes that don't have one
+pub(crate) fn default_material() -> TobjMaterial {
+    TobjMaterial {
+        name: "default".to_string(),
+        ..Default::default()
+    }
+}
+
+/// Loads the meshes of an OBJ file with the material and names of each mesh,
+/// both taken from the tobj model of the mesh
+fn try_load_and_process_obj(path: &OsStr) -> Result<LoadedMeshes, CleanerError> {
+    let load_error = |source| CleanerError::Load {
+        path: path.to_os_string(),
+        source,
+    };
+
+    let file = File::open(path).map_err(|e| CleanerError::io(path, e))?;
+    let mut reader = ObjNameReader::new(BufReader::new(file));
+    let (models, materials) = tobj::load_obj_buf(
+        &mut reader,
         &tobj::LoadOptions {
             single_index: true,
             ..Default::default()
         },
-    )?;
+        |mtl_path| tobj::load_mtl(Path::new(path).with_file_name(mtl_path)),
+    )
+    .map_err(load_error)?;
 
-    let meshes = models
+    let materials = materials.map_err(load_error)?;
+
+    Ok(models
         .into_iter()
-        .map(|m| tobj_mesh_to_trimesh(m.mesh))
-        .collect::<Vec<_>>();
+        .map(|m| {
+            let material = m
+                .mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .cloned()
+                .unwrap_or_else(default_material);
+            let names = reader.model_names(&m.name);
+            (tobj_mesh_to_trimesh(m.mesh), material, names)
+        })
+        .collect())
+}
+
+/// How the distance between a vertex and an hq triangle is measured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DistanceTest {
+    /// Distance to the closest point on the triangle
+    #[default]
+    Exact,
+    /// Distance to the triangle plane, accepting points within the triangle
+    /// expanded by 50 % around its centroid
+    Lenient,
+}
+
+/// Closest point to p on the triangle a, b, c
+fn closest_point_on_triangle(
+    p: Vector3<f64>,
+    a: Vector3<f64>,
+    b: Vector3<f64>,
+    c: Vector3<f64>,
+) -> Vector3<f64> {
+    let ab = b - a;
+    let ac = c - a;
+
+    // Vertex region of a
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    // Vertex region of b
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    // Edge region of ab
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    // Vertex region of c
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    // Edge region of ac
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    // Edge region of bc
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    // Inside the triangle. Degenerate triangles don't have an inside.
+    let denom = va + vb + vc;
+    if denom.abs() < EPSILON {
+        return a;
+    }
+    a + ab * (vb / denom) + ac * (vc / denom)
+}
+
+/// Distance to the triangle plane, and whether the point lands within the
+/// triangle expanded by 50 % around its centroid
+fn lenient_overlap(
+    vertex: Vector3<f64>,
+    mut p0: Vector3<f64>,
+    mut p1: Vector3<f64>,
+    mut p2: Vector3<f64>,
+    threshold: f32,
+) -> bool {
+    let normal = (p1 - p0).cross(p2 - p0).normalize();
+
+    // distance between vertex and plane
+    let dist = normal.dot(vertex - p0).abs() as f32;
+    if dist > threshold {
+        return false;
+    }
+
+    // Expand the triangle slightly
+    let center = (p0 + p1 + p2) / 3.0;
+
+    p0 += (p0 - center) * 0.5;
+    p1 += (p1 - center) * 0.5;
+    p2 += (p2 - center) * 0.5;
+
+    // At this point the distance is already less than threshold.
+    // Just check that the point lands within the triangle
+
+    // Check against first edge
+    let e0n = (p1 - p0).cross(normal);
+    if e0n.dot(vertex - p0) > EPSILON {
+        return false;
+    }
+
+    // Second edge
+    let e1n = (p2 - p1).cross(normal);
+    if e1n.dot(vertex - p1) > EPSILON {
+        return false;
+    }
 
-    Ok((meshes, materials?))
+    // Final edge
+    let e2n = (p0 - p2).cross(normal);
+    e2n.dot(vertex - p2) <= EPSILON
 }
 
-fn vertex_overlapping(vertex: &Vec3, mesh_container: &MeshContainer, threshold: f32) -> bool {
-    let index_grid = mesh_container.index_grid.as_ref().unwrap();
+/// Fails if `mesh_container` was created without index grid
+fn vertex_overlapping(
+    vertex: &Vec3,
+    mesh_container: &MeshContainer,
+    threshold: f32,
+    distance_test: DistanceTest,
+) -> Result<bool, CleanerError> {
+    let index_grid = mesh_container
+        .index_grid
+        .as_ref()
+        .ok_or(CleanerError::MissingMeshData("index grid"))?;
     let indices = index_grid.get_indices(vertex, threshold);
 
     let vertex: Vector3<f64> = vertex.map(|x| x as f64);
 
-    let vertices = match &mesh_container.mesh.positions {
-        Positions::F32(vertices) => vertices,
-        _ => panic!("Not F32"),
-    };
+    let vertices = positions_f32(&mesh_container.mesh)?;
 
     for tri in indices.chunks_exact(3) {
-        let mut p0: Vector3<f64> = vertices[tri[0] as usize].map(|x| x as f64);
-        let mut p1: Vector3<f64> = vertices[tri[1] as usize].map(|x| x as f64);
-        let mut p2: Vector3<f64> = vertices[tri[2] as usize].map(|x| x as f64);
-
-        let normal = (p1 - p0).cross(p2 - p0).normalize();
+        let p0: Vector3<f64> = vertices[tri[0] as usize].map(|x| x as f64);
+        let p1: Vector3<f64> = vertices[tri[1] as usize].map(|x| x as f64);
+        let p2: Vector3<f64> = vertices[tri[2] as usize].map(|x| x as f64);
+
+        let overlapping = match distance_test {
+            DistanceTest::Exact => {
+                let closest = closest_point_on_triangle(vertex, p0, p1, p2);
+                (vertex - closest).magnitude() <= threshold as f64
+            }
+            DistanceTest::Lenient => lenient_overlap(vertex, p0, p1, p2, threshold),
+        };
 
-        // distance between vertex and plane
-        let dist = normal.dot(vertex - p0).abs() as f32;
-        if dist > threshold {
-            continue;
+        if overlapping {
+            return Ok(true);
         }
+    }
 
-        // Expand the triangle slightly
-        let center = (p0 + p1 + p2) / 3.0;
+    Ok(false)
+}
 
-        p0 += (p0 - center) * 0.5;
-        p1 += (p1 - center) * 0.5;
-        p2 += (p2 - center) * 0.5;
+/// What the overlap threshold value is relative to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThresholdMode {
+    /// The value is the distance in model units
+    Absolute,
+    /// The value is multiplied with the mean edge length of the normal mesh
+    #[default]
+    MeanEdge,
+    /// The value is multiplied with the mean edge length of the hq mesh
+    HqEdge,
+}
 
-        // At this point the distance is already less than threshold.
-        // Just check that the point lands within the triangle
+/// Maximum distance from an hq mesh at which vertices of normal meshes are
+/// overlapping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlapThreshold {
+    pub mode: ThresholdMode,
+    pub value: f32,
+}
 
-        // Check against first edge
-        let e0n = (p1 - p0).cross(normal);
-        if e0n.dot(vertex - p0) > EPSILON {
-            continue;
+impl Default for OverlapThreshold {
+    fn default() -> Self {
+        Self {
+            mode: ThresholdMode::MeanEdge,
+            value: 10.0,
         }
+    }
+}
+
+impl OverlapThreshold {
+    /// Resolves the threshold distance between mesh and hq_mesh. Fails if
+    /// the mesh used by the mode was created without mean edge length.
+    pub fn resolve(
+        &self,
+        mesh: &MeshContainer,
+        hq_mesh: &MeshContainer,
+    ) -> Result<f32, CleanerError> {
+        let mean_edge_len = |mesh: &MeshContainer| {
+            mesh.mean_edge_len
+                .ok_or(CleanerError::MissingMeshData("mean edge length"))
+        };
+        Ok(match self.mode {
+            ThresholdMode::Absolute => self.value,
+            ThresholdMode::MeanEdge => self.value * mean_edge_len(mesh)?,
+            ThresholdMode::HqEdge => self.value * mean_edge_len(hq_mesh)?,
+        })
+    }
+}
+
+/// How the size of an island is measured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IslandMode {
+    /// Number of vertices in the island
+    #[default]
+    VertexCount,
+    /// Surface area of the island triangles in square model units
+    Area,
+    /// Bounding box diagonal of the island in model units
+    Diagonal,
+}
 
-        // Second edge
-        let e1n = (p2 - p1).cross(normal);
-        if e1n.dot(vertex - p1) > EPSILON {
-            continue;
+impl IslandMode {
+    /// Value used when none is given
+    pub fn default_value(&self) -> f32 {
+        match self {
+            Self::VertexCount => 15.0,
+            Self::Area => 1.0,
+            Self::Diagonal => 1.0,
         }
+    }
+}
+
+/// Islands of non-overlapping vertices left between overlapping ones are
+/// removed when they are smaller than this
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IslandThreshold {
+    pub mode: IslandMode,
+    pub value: f32,
+}
 
-        // Final edge
-        let e2n = (p0 - p2).cross(normal);
-        if e2n.dot(vertex - p2) > EPSILON {
-            continue;
+impl Default for IslandThreshold {
+    fn default() -> Self {
+        Self {
+            mode: IslandMode::VertexCount,
+            value: IslandMode::VertexCount.default_value(),
         }
+    }
+}
+
+/// How the normals of rewritten meshes are produced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NormalMode {
+    /// Keep the normals of the source file
+    #[default]
+    Keep,
+    /// Area weighted average of the adjacent triangle normals
+    Smooth,
+    /// Corner angle weighted average of the adjacent triangle normals
+    AngleWeighted,
+}
 
-        return true;
+/// Why a vertex of a normal mesh was kept or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexClass {
+    /// Not overlapping
+    Kept,
+    /// Overlapping, clipped away with [`CutMode::Clip`]
+    Overlapping,
+    /// Overlapping and deleted with its triangles
+    Deleted,
+    /// Part of a small island of non-overlapping vertices, removed together
+    /// with the overlapping vertices around it
+    Island,
+    /// Overlapping, but kept to connect to non-overlapping neighbors
+    BoundaryKept,
+}
+
+impl VertexClass {
+    /// Colour of the class in debug output
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Self::Kept => [0.7, 0.7, 0.7],
+            Self::Overlapping => [1.0, 0.8, 0.0],
+            Self::Deleted => [0.9, 0.1, 0.1],
+            Self::Island => [0.8, 0.0, 0.8],
+            Self::BoundaryKept => [0.1, 0.3, 1.0],
+        }
     }
+}
 
-    false
+/// Source file formats that can be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetFormat {
+    Obj,
+    Gltf,
+    Glb,
+}
+
+impl AssetFormat {
+    pub fn from_path(path: &OsStr) -> Option<Self> {
+        let extension = Path::new(path).extension()?;
+        if extension.eq_ignore_ascii_case("obj") {
+            Some(Self::Obj)
+        } else if extension.eq_ignore_ascii_case("gltf") {
+            Some(Self::Gltf)
+        } else if extension.eq_ignore_ascii_case("glb") {
+            Some(Self::Glb)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -133,6 +628,12 @@ pub struct ModelReference {
     pub source_file: OsString,
     pub materials: Vec<TobjMaterial>,
     pub texture_downscale_factor: u32,
+    /// Names of the textures that are embedded in the source file
+    pub embedded_textures: HashSet<String>,
+    /// See [`Model::out_path`]
+    pub out_path: PathBuf,
+    /// See [`Model::texture_plan`]
+    pub texture_plan: TexturePlan,
 }
 
 #[derive(Debug)]
@@ -140,11 +641,14 @@ pub struct MeshContainer {
     pub mesh: TriMesh,
     aabb: AxisAlignedBoundingBox,
     pub material: TobjMaterial,
+    pub names: MeshNames,
     /// List of indices of vertices that are overlapping with other
     /// models
     pub overlapping_vertice_idxs: HashSet<usize>,
     /// Indicates whether this mesh is totally overlapping
     to_be_deleted: bool,
+    /// Vertices of small islands that were added to overlapping_vertice_idxs
+    island_vertice_idxs: HashSet<usize>,
     mean_edge_len: Option<f32>,
 
     /// List of indices that are to be deleted.
@@ -152,6 +656,21 @@ pub struct MeshContainer {
     /// those that are on the edge are removed (i.e. has neigbors that are non-overlapping)
     indices_to_delete: HashSet<usize>,
     index_grid: Option<IndexGrid>,
+
+    /// Where edges between overlapping and non-overlapping vertices cross
+    /// the hq boundary. Key is (kept, overlapping) vertex index pair, value
+    /// is the position along the edge. Only used with [`CutMode::Clip`].
+    pub boundary_cuts: HashMap<(u32, u32), f32>,
+
+    /// Open boundary edges of the overlapping hq meshes near this mesh.
+    /// Only collected when stitching seams.
+    pub hq_boundary_segments: Vec<[Vec3; 2]>,
+    /// Largest overlap threshold of the hq meshes this mesh overlaps with.
+    /// Seam vertices are only stitched within this distance.
+    pub max_overlap_threshold: f32,
+    /// Open boundary edges of this mesh before deleting vertices, by both
+    /// of their end points
+    original_boundary: HashMap<VertexKey, Vec<[Vec3; 2]>>,
 }
 
 impl MeshContainer {
@@ -159,93 +678,266 @@ impl MeshContainer {
         self.to_be_deleted || !self.overlapping_vertice_idxs.is_empty()
     }
 
+    /// Whether the whole mesh is overlapping and will be dropped
+    pub fn to_be_deleted(&self) -> bool {
+        self.to_be_deleted
+    }
+
+    /// Classifies each vertex by how cut_mode treats it. Call after
+    /// [`Self::mark_islands_as_overlapping`] and
+    /// [`Self::mark_vertices_to_delete`] and before cutting.
+    pub fn vertex_classes(&self, cut_mode: CutMode) -> Vec<VertexClass> {
+        (0..self.mesh.vertex_count())
+            .map(|idx| {
+                let removed = self.to_be_deleted
+                    || match cut_mode {
+                        CutMode::Vertices => self.indices_to_delete.contains(&idx),
+                        CutMode::Clip => self.overlapping_vertice_idxs.contains(&idx),
+                    };
+
+                if !self.overlapping_vertice_idxs.contains(&idx) {
+                    VertexClass::Kept
+                } else if !removed {
+                    VertexClass::BoundaryKept
+                } else if self.island_vertice_idxs.contains(&idx) {
+                    VertexClass::Island
+                } else {
+                    match cut_mode {
+                        CutMode::Vertices => VertexClass::Deleted,
+                        CutMode::Clip => VertexClass::Overlapping,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Group, object or material name of the mesh, whichever is set first
+    pub fn display_name(&self) -> &str {
+        self.names
+            .group
+            .as_deref()
+            .or(self.names.object.as_deref())
+            .unwrap_or(&self.material.name)
+    }
+
+    pub fn index_grid(&self) -> Option<&IndexGrid> {
+        self.index_grid.as_ref()
+    }
+
+    /// See [`Model::build_index_grids`]
+    fn build_index_grid(&mut self, index_grid_cell_size: GridCellSize) -> Result<(), CleanerError> {
+        self.aabb = self.mesh.compute_aabb();
+        if self.mesh.triangle_count() == 0 {
+            self.aabb = AxisAlignedBoundingBox::EMPTY;
+            self.mean_edge_len = None;
+            self.index_grid = None;
+            return Ok(());
+        }
+
+        self.mean_edge_len = Some(mean_edge_length(&self.mesh)?);
+        let mut index_grid = IndexGrid::new(index_grid_cell_size.resolve(&self.mesh)?);
+        index_grid.populate_from_trimesh(&self.mesh)?;
+        self.index_grid = Some(index_grid);
+        Ok(())
+    }
+
     pub fn new(
         mesh: TriMesh,
         material: TobjMaterial,
         calc_edge_len: bool,
-        init_index_grid: bool,
-    ) -> Self {
+        index_grid_cell_size: Option<GridCellSize>,
+    ) -> Result<Self, CleanerError> {
         let aabb = mesh.compute_aabb();
 
+        // The rest of the processing expects these layouts
+        positions_f32(&mesh)?;
+        indices_u32(&mesh)?;
+
         let mean_edge_len = match calc_edge_len {
-            true => {
-                let mut len_sum = 0.0;
-                let mut len_cnt = 0;
-                let positions = match &mesh.positions {
-                    Positions::F32(positions) => positions,
-                    _ => panic!("Positions not F32"),
-                };
-                mesh.for_each_triangle(|i0, i1, i2| {
-                    let p0 = positions[i0];
-                    let p1 = positions[i1];
-                    let p2 = positions[i2];
-
-                    len_sum += p0.distance(p1);
-                    len_sum += p1.distance(p2);
-                    len_sum += p2.distance(p0);
-                    len_cnt += 3;
-                });
-                Some(len_sum / len_cnt as f32)
-            }
+            true => Some(mean_edge_length(&mesh)?),
             false => None,
         };
 
-        let index_grid = match init_index_grid {
-            true => {
-                let mut index_grid = IndexGrid::new();
-                index_grid.populate_from_trimesh(&mesh);
+        let index_grid = match index_grid_cell_size {
+            Some(cell_size) => {
+                let mut index_grid = IndexGrid::new(cell_size.resolve(&mesh)?);
+                index_grid.populate_from_trimesh(&mesh)?;
                 Some(index_grid)
             }
-            false => None,
+            None => None,
         };
 
-        Self {
+        Ok(Self {
             mesh,
             aabb,
             material,
+            names: MeshNames::default(),
             overlapping_vertice_idxs: HashSet::new(),
             to_be_deleted: false,
+            island_vertice_idxs: HashSet::new(),
             mean_edge_len,
             indices_to_delete: HashSet::new(),
             index_grid,
-        }
+            boundary_cuts: HashMap::new(),
+            hq_boundary_segments: Vec::new(),
+            max_overlap_threshold: 0.0,
+            original_boundary: HashMap::new(),
+        })
     }
 
-    /// Calculates vertice indices from self, which are overlapping with other
-    pub fn calc_overlapping_vertice_idxs(&self, other: &Self) -> Vec<usize> {
+    /// Calculates vertice indices from self, which are within threshold from
+    /// other. Fails if other was created without index grid.
+    pub fn calc_overlapping_vertice_idxs(
+        &self,
+        other: &Self,
+        threshold: f32,
+        distance_test: DistanceTest,
+    ) -> Result<Vec<usize>, CleanerError> {
         let mut overlapping = vec![];
-        let threshold = 10.0
-            * self
-                .mean_edge_len
-                .expect("Trying to calculate overlapping without mean edge len");
-
-        if let Some(intersection) = self.aabb.intersection(other.aabb) {
-            match &self.mesh.positions {
-                Positions::F32(vertices) => {
-                    for (idx, vertex) in vertices.iter().enumerate() {
-                        if intersection.is_inside(*vertex)
-                            && vertex_overlapping(vertex, other, threshold)
-                        {
-                            overlapping.push(idx);
-                        }
+
+        // Vertices up to threshold away from other can be overlapping
+        if let Some(intersection) = self.aabb.intersection(expand_aabb(other.aabb, threshold)) {
+            let vertices = positions_f32(&self.mesh)?;
+            for (idx, vertex) in vertices.iter().enumerate() {
+                if intersection.is_inside(*vertex)
+                    && vertex_overlapping(vertex, other, threshold, distance_test)?
+                {
+                    overlapping.push(idx);
+                }
+            }
+        }
+        Ok(overlapping)
+    }
+
+    /// Locates where the edges between overlapping and non-overlapping
+    /// vertices cross the boundary of other, by bisecting the edge with the
+    /// same overlap test as [`Self::calc_overlapping_vertice_idxs`].
+    ///
+    /// `overlapping` are the vertices of self overlapping with other.
+    pub fn calc_boundary_cuts(
+        &self,
+        overlapping: &[usize],
+        other: &Self,
+        threshold: f32,
+        distance_test: DistanceTest,
+    ) -> Result<Vec<BoundaryCut>, CleanerError> {
+        if overlapping.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
+        let overlapping: HashSet<usize> = HashSet::from_iter(overlapping.iter().cloned());
+
+        let mut visited_edges = HashSet::new();
+        let mut cuts = vec![];
+
+        for tri in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let i0 = tri[i];
+                let i1 = tri[(i + 1) % 3];
+
+                let (kept, overlapping_idx) = match (
+                    overlapping.contains(&(i0 as usize)),
+                    overlapping.contains(&(i1 as usize)),
+                ) {
+                    (false, true) => (i0, i1),
+                    (true, false) => (i1, i0),
+                    _ => continue,
+                };
+
+                if !visited_edges.insert((kept, overlapping_idx)) {
+                    continue;
+                }
+
+                let p_kept = vertices[kept as usize];
+                let p_overlapping = vertices[overlapping_idx as usize];
+
+                // Boundary is between lo (not overlapping) and hi (overlapping)
+                let mut lo = 0.0;
+                let mut hi = 1.0;
+                for _ in 0..BOUNDARY_SEARCH_STEPS {
+                    let mid = 0.5 * (lo + hi);
+                    let p = p_kept + (p_overlapping - p_kept) * mid;
+                    if vertex_overlapping(&p, other, threshold, distance_test)? {
+                        hi = mid;
+                    } else {
+                        lo = mid;
                     }
                 }
-                _ => panic!("Positions are not F32"),
+
+                cuts.push(BoundaryCut {
+                    kept,
+                    overlapping: overlapping_idx,
+                    t: 0.5 * (lo + hi),
+                });
             }
         }
-        overlapping
+
+        Ok(cuts)
+    }
+
+    /// Returns the open boundary edges of other, that are close enough to self
+    /// to be stitched to.
+    pub fn calc_hq_boundary_segments(
+        &self,
+        other: &Self,
+        threshold: f32,
+    ) -> Result<Vec<[Vec3; 2]>, CleanerError> {
+        let region = expand_aabb(self.aabb, threshold);
+
+        let vertices = positions_f32(&other.mesh)?;
+        let indices = indices_u32(&other.mesh)?;
+
+        Ok(boundary_edges(indices)
+            .into_iter()
+            .map(|(i0, i1)| [vertices[i0 as usize], vertices[i1 as usize]])
+            .filter(|segment| region.is_inside(segment[0]) || region.is_inside(segment[1]))
+            .collect())
+    }
+
+    /// Adds cuts to the boundary cuts. If an edge is cut multiple times,
+    /// the cut closest to the kept vertex is used.
+    pub fn add_boundary_cuts(&mut self, cuts: &[BoundaryCut]) {
+        for cut in cuts {
+            self.boundary_cuts
+                .entry((cut.kept, cut.overlapping))
+                .and_modify(|t| *t = t.min(cut.t))
+                .or_insert(cut.t);
+        }
     }
 
     /// Mark small islands as to delete.
-    fn mark_islands_as_overlapping(&mut self, threshold_cnt: usize) {
+    fn mark_islands_as_overlapping(
+        &mut self,
+        threshold: IslandThreshold,
+    ) -> Result<(), CleanerError> {
         if self.overlapping_vertice_idxs.is_empty() {
-            return;
+            return Ok(());
         }
 
-        let indices = match &self.mesh.indices {
-            Indices::U32(indices) => indices,
-            _ => panic!("Indices not U32"),
-        };
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
+
+        // A third of the area of each non-overlapping triangle for each of
+        // its vertices, so that island areas are sums over island vertices
+        let mut vertex_areas = vec![];
+        if threshold.mode == IslandMode::Area {
+            vertex_areas = vec![0.0; vertices.len()];
+            for tri in indices.chunks_exact(3) {
+                if tri
+                    .iter()
+                    .any(|i| self.overlapping_vertice_idxs.contains(&(*i as usize)))
+                {
+                    continue;
+                }
+                let [a, b, c] = [0, 1, 2].map(|i| vertices[tri[i] as usize]);
+                let area = 0.5 * (b - a).cross(c - a).magnitude();
+                for i in tri {
+                    vertex_areas[*i as usize] += area / 3.0;
+                }
+            }
+        }
 
         // Key has index of vert, value is vec of neigboring keys
         let mut adjacency_graph: HashMap<usize, Vec<usize>> = HashMap::new();
@@ -292,7 +984,7 @@ impl MeshContainer {
 
                 let neighbors = adjacency_graph
                     .get(&index)
-                    .expect("Node should have neighbors");
+                    .ok_or(CleanerError::MissingMeshData("island vertex neighbors"))?;
                 for neighbor in neighbors.iter() {
                     if !visited_indices.contains(neighbor)
                         && !self.overlapping_vertice_idxs.contains(neighbor)
@@ -302,33 +994,46 @@ impl MeshContainer {
                 }
             }
 
-            if cur_mesh_indices.len() < threshold_cnt {
+            let size = match threshold.mode {
+                IslandMode::VertexCount => cur_mesh_indices.len() as f32,
+                IslandMode::Area => cur_mesh_indices.iter().map(|i| vertex_areas[*i]).sum(),
+                IslandMode::Diagonal => {
+                    let positions = cur_mesh_indices
+                        .iter()
+                        .map(|i| vertices[*i])
+                        .collect::<Vec<_>>();
+                    AxisAlignedBoundingBox::new_with_positions(&positions)
+                        .size()
+                        .magnitude()
+                }
+            };
+
+            if size < threshold.value {
                 self.overlapping_vertice_idxs
                     .extend(cur_mesh_indices.iter());
+                self.island_vertice_idxs.extend(cur_mesh_indices.iter());
             }
         }
 
         println!("Marked island vertices");
+        Ok(())
     }
 
     /// Mark indices that are to be deleted
     /// If all are deleted, rather set to_be_deleted to true
-    fn mark_vertices_to_delete(&mut self) {
+    fn mark_vertices_to_delete(&mut self) -> Result<(), CleanerError> {
         if self.overlapping_vertice_idxs.is_empty() {
-            return;
+            return Ok(());
         }
 
-        if self.overlapping_vertice_idxs.len() == self.mesh.indices.len().unwrap() {
+        let indices = indices_u32(&self.mesh)?;
+
+        if self.overlapping_vertice_idxs.len() == indices.len() {
             println!("Whole mesh to be deleted");
             self.to_be_deleted = true;
-            return;
+            return Ok(());
         }
 
-        let indices = match &self.mesh.indices {
-            Indices::U32(indices) => indices,
-            _ => panic!("Indices not U32"),
-        };
-
         let mut indices_to_delete =
             HashSet::from_iter(self.overlapping_vertice_idxs.iter().cloned());
 
@@ -365,22 +1070,18 @@ impl MeshContainer {
         }
 
         self.indices_to_delete = indices_to_delete;
+        Ok(())
     }
 
-    fn do_delete_vertices(&mut self) {
-        let vertices = match &self.mesh.positions {
-            Positions::F32(vertices) => vertices,
-            _ => panic!("Positions not F32"),
-        };
-        let indices = match &self.mesh.indices {
-            Indices::U32(indices) => indices,
-            _ => panic!("Indices not U32"),
-        };
+    fn do_delete_vertices(&mut self) -> Result<(), CleanerError> {
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
 
         let mut new_vertices =
             Vec::with_capacity(self.mesh.vertex_count() - self.indices_to_delete.len());
         let mut remap = vec![None; self.mesh.vertex_count()];
         let mut new_uvs = Vec::new();
+        let mut new_normals = Vec::new();
 
         for (old_idx, v) in vertices.iter().enumerate() {
             if self.indices_to_delete.contains(&old_idx) {
@@ -391,6 +1092,10 @@ impl MeshContainer {
                 new_uvs.push(uvs[old_idx]);
             }
 
+            if let Some(normals) = &self.mesh.normals {
+                new_normals.push(normals[old_idx]);
+            }
+
             let new_idx = new_vertices.len();
             new_vertices.push(*v);
             remap[old_idx] = Some(new_idx);
@@ -418,79 +1123,472 @@ impl MeshContainer {
             true => self.mesh.uvs = None,
             false => self.mesh.uvs = Some(new_uvs),
         }
-        self.mesh.normals = None;
+        match new_normals.is_empty() {
+            true => self.mesh.normals = None,
+            false => self.mesh.normals = Some(new_normals),
+        }
         self.mesh.tangents = None;
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-pub struct Model {
-    pub meshes: Vec<MeshContainer>,
-    pub aabb: AxisAlignedBoundingBox,
-    pub source_file: OsString,
-    pub texture_downscale_factor: u32,
-}
+    /// Replaces the normals with ones calculated from the triangles. Vertices
+    /// at the same position share the normal, so seams between uv islands
+    /// are smooth too. Does nothing for [`NormalMode::Keep`].
+    pub fn recompute_normals(&mut self, mode: NormalMode) -> Result<(), CleanerError> {
+        if mode == NormalMode::Keep {
+            return Ok(());
+        }
 
-impl Model {
-    pub fn try_new_from_file(
-        path: OsString,
-        calc_edge_len: bool,
-        init_index_grid: bool,
-        texture_downscale_factor: u32,
-    ) -> Result<Self, tobj::LoadError> {
-        let (tri_meshes, materials) = try_load_and_process_obj(&path)?;
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
 
-        let meshes = tri_meshes
-            .into_iter()
-            .zip(materials)
-            .map(|(mesh, material)| {
-                MeshContainer::new(mesh, material, calc_edge_len, init_index_grid)
-            })
-            .collect::<Vec<_>>();
+        let mut sums: HashMap<VertexKey, Vec3> = HashMap::new();
+        for tri in indices.chunks_exact(3) {
+            let p = [0, 1, 2].map(|i| vertices[tri[i] as usize]);
+            // Length of the cross product is twice the triangle area
+            let face_normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            if face_normal.magnitude2() == 0.0 {
+                continue;
+            }
 
-        let mut aabb = AxisAlignedBoundingBox::EMPTY;
-        for mesh in meshes.iter() {
-            aabb.expand_with_aabb(mesh.aabb);
+            for i in 0..3 {
+                let weight = match mode {
+                    NormalMode::AngleWeighted => {
+                        let e0 = p[(i + 1) % 3] - p[i];
+                        let e1 = p[(i + 2) % 3] - p[i];
+                        e0.angle(e1).0 / face_normal.magnitude()
+                    }
+                    _ => 1.0,
+                };
+                *sums
+                    .entry(vertex_key(p[i]))
+                    .or_insert(Vec3::new(0.0, 0.0, 0.0)) += face_normal * weight;
+            }
         }
 
-        Ok(Self {
-            meshes,
-            aabb,
-            source_file: path,
-            texture_downscale_factor,
-        })
+        let normals = vertices
+            .iter()
+            .map(|v| match sums.get(&vertex_key(*v)) {
+                Some(sum) if sum.magnitude2() > 0.0 => sum.normalize(),
+                _ => Vec3::new(0.0, 0.0, 1.0),
+            })
+            .collect();
+        self.mesh.normals = Some(normals);
+        self.mesh.tangents = None;
+        Ok(())
     }
 
-    pub fn modified(&self) -> bool {
-        self.meshes.iter().any(|m| m.modified())
+    /// Stores the current open boundary, so that the seams opened by
+    /// deleting vertices can be told apart from it.
+    fn record_boundary(&mut self) -> Result<(), CleanerError> {
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
+
+        self.original_boundary.clear();
+        for (i0, i1) in boundary_edges(indices) {
+            let segment = [vertices[i0 as usize], vertices[i1 as usize]];
+            for p in segment {
+                self.original_boundary
+                    .entry(vertex_key(p))
+                    .or_default()
+                    .push(segment);
+            }
+        }
+        Ok(())
     }
 
-    pub fn mark_vertices_to_delete(&mut self) {
-        for mesh in self.meshes.iter_mut() {
-            mesh.mark_vertices_to_delete();
-        }
+    /// Checks whether the edge is, or is a part of, an original boundary edge.
+    /// Clipping can shorten boundary edges, but keeps one of the end points.
+    fn on_original_boundary(&self, p0: Vec3, p1: Vec3) -> bool {
+        [(p0, p1), (p1, p0)].iter().any(|(p, other)| {
+            let Some(segments) = self.original_boundary.get(&vertex_key(*p)) else {
+                return false;
+            };
+            segments.iter().any(|segment| {
+                let tolerance = 1e-5 * segment[0].distance(segment[1]);
+                other.distance(closest_point_on_segment(*other, segment)) <= tolerance
+            })
+        })
     }
 
-    pub fn mark_islands_as_overlapping(&mut self, threshold_cnt: usize) {
-        for mesh in self.meshes.iter_mut() {
-            mesh.mark_islands_as_overlapping(threshold_cnt);
+    /// Bridges the seams opened by deleting vertices to the nearest hq
+    /// boundary edges. Each seam vertex gets a new vertex on the hq
+    /// boundary, and each seam edge is closed with two triangles. Seam
+    /// vertices farther than [`Self::max_overlap_threshold`] from the hq boundary are
+    /// left open.
+    fn stitch_seams(&mut self) -> Result<(), CleanerError> {
+        if self.hq_boundary_segments.is_empty() {
+            return Ok(());
         }
-    }
 
-    pub fn do_delete_vertices(&mut self) {
-        let mut meshes_to_delete = vec![];
+        let max_distance = self.max_overlap_threshold;
+        let mut vertices = positions_f32(&self.mesh)?.to_vec();
+        let mut indices = indices_u32(&self.mesh)?.to_vec();
 
-        for (idx, mesh) in self.meshes.iter_mut().enumerate() {
-            if mesh.to_be_deleted {
-                meshes_to_delete.push(idx);
-                continue;
-            }
-            mesh.do_delete_vertices();
+        let seam_edges = boundary_edges(&indices)
+            .into_iter()
+            .filter(|(i0, i1)| {
+                !self.on_original_boundary(vertices[*i0 as usize], vertices[*i1 as usize])
+            })
+            .collect::<Vec<_>>();
+
+        // Index of the vertex on the hq boundary, for each seam vertex
+        let mut bridge_vertices: HashMap<u32, Option<u32>> = HashMap::new();
+
+        for (i0, i1) in seam_edges.iter() {
+            let mut bridged = [None; 2];
+
+            for (bridge, seam_idx) in bridged.iter_mut().zip([*i0, *i1]) {
+                *bridge = *bridge_vertices.entry(seam_idx).or_insert_with(|| {
+                    let p = vertices[seam_idx as usize];
+                    let closest = self
+                        .hq_boundary_segments
+                        .iter()
+                        .map(|segment| closest_point_on_segment(p, segment))
+                        .min_by(|a, b| p.distance2(*a).total_cmp(&p.distance2(*b)))?;
+
+                    if p.distance(closest) > max_distance {
+                        return None;
+                    }
+
+                    if let Some(uvs) = &mut self.mesh.uvs {
+                        uvs.push(uvs[seam_idx as usize]);
+                    }
+                    if let Some(normals) = &mut self.mesh.normals {
+                        normals.push(normals[seam_idx as usize]);
+                    }
+
+                    vertices.push(closest);
+                    Some(vertices.len() as u32 - 1)
+                });
+            }
+
+            let [Some(b0), Some(b1)] = bridged else {
+                continue;
+            };
+
+            // The seam edge is i0 -> i1 in the remaining triangle, so the
+            // bridging triangles traverse it the other way around
+            indices.extend_from_slice(&[*i1, *i0, b0]);
+            if vertices[b0 as usize] != vertices[b1 as usize] {
+                indices.extend_from_slice(&[*i1, b0, b1]);
+            }
+        }
+
+        self.mesh.positions = Positions::F32(vertices);
+        self.mesh.indices = Indices::U32(indices);
+        self.mesh.tangents = None;
+        Ok(())
+    }
+
+    /// Deletes all overlapping vertices and splits the triangles crossing
+    /// the boundary at the boundary cuts. Positions, uvs and normals of the
+    /// new vertices are interpolated along the cut edges. Triangles whose
+    /// crossing edges have no known cut are deleted.
+    fn do_clip_vertices(&mut self) -> Result<(), CleanerError> {
+        let vertices = positions_f32(&self.mesh)?;
+        let indices = indices_u32(&self.mesh)?;
+
+        let mut new_vertices = Vec::with_capacity(self.mesh.vertex_count());
+        let mut new_uvs = self.mesh.uvs.as_ref().map(|_| Vec::new());
+        let mut new_normals = self.mesh.normals.as_ref().map(|_| Vec::new());
+        let mut remap = vec![None; self.mesh.vertex_count()];
+
+        for (old_idx, v) in vertices.iter().enumerate() {
+            if self.overlapping_vertice_idxs.contains(&old_idx) {
+                continue;
+            }
+
+            if let (Some(new_uvs), Some(uvs)) = (&mut new_uvs, &self.mesh.uvs) {
+                new_uvs.push(uvs[old_idx]);
+            }
+            if let (Some(new_normals), Some(normals)) = (&mut new_normals, &self.mesh.normals) {
+                new_normals.push(normals[old_idx]);
+            }
+
+            remap[old_idx] = Some(new_vertices.len() as u32);
+            new_vertices.push(*v);
+        }
+
+        // New vertices created on the cut edges, shared by both triangles of the edge
+        let mut cut_vertices: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut new_indices = Vec::new();
+
+        for tri in indices.chunks_exact(3) {
+            let overlapping = tri
+                .iter()
+                .map(|i| self.overlapping_vertice_idxs.contains(&(*i as usize)))
+                .collect::<Vec<_>>();
+
+            if overlapping.iter().all(|o| *o) {
+                continue;
+            }
+
+            // Walk the triangle edges, collecting the kept part as a polygon
+            let mut polygon = Vec::with_capacity(4);
+            let mut complete = true;
+
+            for i in 0..3 {
+                let i0 = tri[i];
+                let i1 = tri[(i + 1) % 3];
+
+                if let Some(new_idx) = remap[i0 as usize] {
+                    polygon.push(new_idx);
+                }
+
+                if overlapping[i] == overlapping[(i + 1) % 3] {
+                    continue;
+                }
+
+                let key = match overlapping[i] {
+                    true => (i1, i0),
+                    false => (i0, i1),
+                };
+
+                let Some(t) = self.boundary_cuts.get(&key).copied() else {
+                    complete = false;
+                    break;
+                };
+
+                let new_idx = *cut_vertices.entry(key).or_insert_with(|| {
+                    let (kept, overlapping) = (key.0 as usize, key.1 as usize);
+
+                    if let (Some(new_uvs), Some(uvs)) = (&mut new_uvs, &self.mesh.uvs) {
+                        new_uvs.push(uvs[kept] + (uvs[overlapping] - uvs[kept]) * t);
+                    }
+                    if let (Some(new_normals), Some(normals)) =
+                        (&mut new_normals, &self.mesh.normals)
+                    {
+                        let normal = normals[kept] + (normals[overlapping] - normals[kept]) * t;
+                        new_normals.push(normal.normalize());
+                    }
+
+                    new_vertices
+                        .push(vertices[kept] + (vertices[overlapping] - vertices[kept]) * t);
+                    new_vertices.len() as u32 - 1
+                });
+                polygon.push(new_idx);
+            }
+
+            if !complete {
+                continue;
+            }
+
+            // Polygon is convex with 3 or 4 corners, so fan triangulation works
+            for i in 1..polygon.len().saturating_sub(1) {
+                new_indices.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+            }
+        }
+
+        self.mesh.positions = Positions::F32(new_vertices);
+        self.mesh.indices = Indices::U32(new_indices);
+        self.mesh.uvs = new_uvs;
+        self.mesh.normals = new_normals;
+        self.mesh.tangents = None;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Model {
+    pub meshes: Vec<MeshContainer>,
+    pub aabb: AxisAlignedBoundingBox,
+    pub source_file: OsString,
+    pub texture_downscale_factor: u32,
+    /// Encoded images embedded in the source file, by texture name
+    pub embedded_textures: HashMap<String, Vec<u8>>,
+    /// Hq assets this model has overlapping vertices with
+    pub overlapping_assets: BTreeSet<OsString>,
+    /// Smallest and largest resolved overlap threshold of the mesh pairs
+    /// with overlapping vertices
+    pub threshold_range: Option<(f32, f32)>,
+    /// Time it took to load the source file
+    pub load_time: Duration,
+    /// Where the asset is written relative to the out folder. The extension
+    /// is replaced to match the output format when the asset is rewritten.
+    pub out_path: PathBuf,
+    /// Output names of the texture files
+    pub texture_plan: TexturePlan,
+}
+
+impl Model {
+    pub fn try_new_from_file(
+        path: OsString,
+        calc_edge_len: bool,
+        index_grid_cell_size: Option<GridCellSize>,
+        texture_downscale_factor: u32,
+    ) -> Result<Self, CleanerError> {
+        let start_time = Instant::now();
+        let (loaded_meshes, embedded_textures) = match AssetFormat::from_path(&path) {
+            Some(AssetFormat::Gltf | AssetFormat::Glb) => gltf_io::try_load_gltf(&path)?,
+            _ => (try_load_and_process_obj(&path)?, HashMap::new()),
+        };
+
+        // Meshes without triangles, e.g. groups of only lines, have nothing to
+        // clean or to clean against
+        let meshes = loaded_meshes
+            .into_iter()
+            .filter(|(mesh, _, _)| mesh.triangle_count() > 0)
+            .map(|(mesh, material, names)| {
+                let mut container =
+                    MeshContainer::new(mesh, material, calc_edge_len, index_grid_cell_size)?;
+                container.names = names;
+                Ok(container)
+            })
+            .collect::<Result<Vec<_>, CleanerError>>()?;
+        if meshes.is_empty() {
+            return Err(CleanerError::EmptyMesh);
+        }
+
+        let mut aabb = AxisAlignedBoundingBox::EMPTY;
+        for mesh in meshes.iter() {
+            aabb.expand_with_aabb(mesh.aabb);
+        }
+
+        let out_path = PathBuf::from(Path::new(&path).file_name().unwrap_or_default());
+
+        Ok(Self {
+            meshes,
+            aabb,
+            out_path,
+            texture_plan: TexturePlan::default(),
+            source_file: path,
+            texture_downscale_factor,
+            embedded_textures,
+            overlapping_assets: BTreeSet::new(),
+            threshold_range: None,
+            load_time: Instant::now() - start_time,
+        })
+    }
+
+    /// Builds the index grids and mean edge lengths of the meshes as they
+    /// are now, for cleaning the assets of lower layers against the model
+    /// after it was cleaned itself. Meshes without triangles are left
+    /// without.
+    pub fn build_index_grids(
+        &mut self,
+        index_grid_cell_size: GridCellSize,
+    ) -> Result<(), CleanerError> {
+        let mut aabb = AxisAlignedBoundingBox::EMPTY;
+        for mesh in self.meshes.iter_mut() {
+            mesh.build_index_grid(index_grid_cell_size)?;
+            aabb.expand_with_aabb(mesh.aabb);
+        }
+        self.aabb = aabb;
+        Ok(())
+    }
+
+    /// Frees the index grids once the model is no longer cleaned against
+    pub fn drop_index_grids(&mut self) {
+        for mesh in self.meshes.iter_mut() {
+            mesh.index_grid = None;
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.meshes.iter().map(|m| m.mesh.vertex_count()).sum()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.meshes.iter().map(|m| m.mesh.triangle_count()).sum()
+    }
+
+    /// Mean edge length of the meshes weighted by their triangle counts.
+    /// None if a mesh was created without mean edge length or there are no
+    /// triangles.
+    pub fn mean_edge_length(&self) -> Option<f32> {
+        let mut total = 0.0;
+        for mesh in self.meshes.iter() {
+            total += mesh.mean_edge_len? * mesh.mesh.triangle_count() as f32;
+        }
+        let triangles = self.triangle_count();
+        (triangles > 0).then(|| total / triangles as f32)
+    }
+
+    pub fn modified(&self) -> bool {
+        self.meshes.iter().any(|m| m.modified())
+    }
+
+    pub fn mark_vertices_to_delete(&mut self) -> Result<(), CleanerError> {
+        for mesh in self.meshes.iter_mut() {
+            mesh.mark_vertices_to_delete()?;
+        }
+        Ok(())
+    }
+
+    pub fn mark_islands_as_overlapping(
+        &mut self,
+        threshold: IslandThreshold,
+    ) -> Result<(), CleanerError> {
+        for mesh in self.meshes.iter_mut() {
+            mesh.mark_islands_as_overlapping(threshold)?;
+        }
+        Ok(())
+    }
+
+    pub fn do_delete_vertices(&mut self) -> Result<(), CleanerError> {
+        let mut meshes_to_delete = vec![];
+
+        for (idx, mesh) in self.meshes.iter_mut().enumerate() {
+            if mesh.to_be_deleted {
+                meshes_to_delete.push(idx);
+                continue;
+            }
+            mesh.do_delete_vertices()?;
         }
 
         for idx in meshes_to_delete.iter().rev() {
             self.meshes.remove(*idx);
         }
+        Ok(())
+    }
+
+    /// Deletes the overlapping geometry according to cut_mode
+    pub fn do_cut(&mut self, cut_mode: CutMode) -> Result<(), CleanerError> {
+        match cut_mode {
+            CutMode::Vertices => self.do_delete_vertices(),
+            CutMode::Clip => self.do_clip_vertices(),
+        }
+    }
+
+    /// See [`MeshContainer::record_boundary`]. Must be called before
+    /// [`Self::do_cut`] to be able to stitch the seams afterwards.
+    pub fn record_boundaries(&mut self) -> Result<(), CleanerError> {
+        for mesh in self.meshes.iter_mut() {
+            mesh.record_boundary()?;
+        }
+        Ok(())
+    }
+
+    pub fn stitch_seams(&mut self) -> Result<(), CleanerError> {
+        for mesh in self.meshes.iter_mut() {
+            mesh.stitch_seams()?;
+        }
+        Ok(())
+    }
+
+    pub fn recompute_normals(&mut self, mode: NormalMode) -> Result<(), CleanerError> {
+        for mesh in self.meshes.iter_mut() {
+            mesh.recompute_normals(mode)?;
+        }
+        Ok(())
+    }
+
+    pub fn do_clip_vertices(&mut self) -> Result<(), CleanerError> {
+        let mut meshes_to_delete = vec![];
+
+        for (idx, mesh) in self.meshes.iter_mut().enumerate() {
+            if mesh.to_be_deleted {
+                meshes_to_delete.push(idx);
+                continue;
+            }
+            mesh.do_clip_vertices()?;
+        }
+
+        for idx in meshes_to_delete.iter().rev() {
+            self.meshes.remove(*idx);
+        }
+        Ok(())
     }
 
     pub fn to_be_deleted(&self) -> bool {
@@ -499,16 +1597,19 @@ impl Model {
 }
 
 impl ModelReference {
-    pub fn from_model(model: Model, texture_downscale_factor: u32) -> Self {
+    pub fn from_model(model: &Model, texture_downscale_factor: u32) -> Self {
         let materials = model
             .meshes
-            .into_iter()
-            .map(|m| m.material)
+            .iter()
+            .map(|m| m.material.clone())
             .collect::<Vec<_>>();
         Self {
             materials,
             texture_downscale_factor,
-            source_file: model.source_file,
+            source_file: model.source_file.clone(),
+            embedded_textures: model.embedded_textures.keys().cloned().collect(),
+            out_path: model.out_path.clone(),
+            texture_plan: model.texture_plan.clone(),
         }
     }
 }
@@ -519,9 +1620,20 @@ pub enum OutAsset {
     Asset(Model),
 }
 
+impl OutAsset {
+    pub fn out_path(&self) -> &Path {
+        match self {
+            OutAsset::Asset(model) => &model.out_path,
+            OutAsset::AssetRef(model_ref) => &model_ref.out_path,
+        }
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use crate::test_utils::TempDir;
 
     fn create_empty_material() -> tobj::Material {
         tobj::Material {
@@ -558,11 +1670,17 @@ mod tests {
             colors: None,
         };
 
-        let container = MeshContainer::new(trimesh, create_empty_material(), false, true);
+        let container = MeshContainer::new(
+            trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
 
         let vertex = Vec3::new(0.0, 0.0, 1.1);
 
-        let result = vertex_overlapping(&vertex, &container, 1.0);
+        let result = vertex_overlapping(&vertex, &container, 1.0, DistanceTest::Exact).unwrap();
         assert_eq!(result, false);
     }
 
@@ -583,9 +1701,15 @@ mod tests {
 
         let vertex = Vec3::new(0.0, 0.0, 1.0);
 
-        let container = MeshContainer::new(trimesh, create_empty_material(), false, true);
+        let container = MeshContainer::new(
+            trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
 
-        let result = vertex_overlapping(&vertex, &container, 1.0);
+        let result = vertex_overlapping(&vertex, &container, 1.0, DistanceTest::Exact).unwrap();
         assert_eq!(result, true);
     }
 
@@ -606,9 +1730,15 @@ mod tests {
 
         let vertex = Vec3::new(0.0, 0.0, -1.1);
 
-        let container = MeshContainer::new(trimesh, create_empty_material(), false, true);
+        let container = MeshContainer::new(
+            trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
 
-        let result = vertex_overlapping(&vertex, &container, 1.0);
+        let result = vertex_overlapping(&vertex, &container, 1.0, DistanceTest::Exact).unwrap();
         assert_eq!(result, false);
     }
 
@@ -629,9 +1759,644 @@ mod tests {
 
         let vertex = Vec3::new(0.0, 0.0, -1.0);
 
-        let container = MeshContainer::new(trimesh, create_empty_material(), false, true);
+        let container = MeshContainer::new(
+            trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
 
-        let result = vertex_overlapping(&vertex, &container, 1.0);
+        let result = vertex_overlapping(&vertex, &container, 1.0, DistanceTest::Exact).unwrap();
         assert_eq!(result, true);
     }
+
+    #[test]
+    fn test_boundary_cuts_on_boundary() {
+        let hq_trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+        // Strip of small triangles from inside the hq triangle to well outside of it
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for i in 0..40 {
+            let x = 0.5 + 0.05 * i as f32;
+            positions.push(Vec3::new(x, 0.2, 0.0));
+            positions.push(Vec3::new(x, 0.25, 0.0));
+            if i > 0 {
+                let i0 = 2 * i - 2;
+                indices.extend_from_slice(&[i0, i0 + 2, i0 + 1, i0 + 1, i0 + 2, i0 + 3]);
+            }
+        }
+        let trimesh = TriMesh {
+            positions: Positions::F32(positions),
+            indices: Indices::U32(indices),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+
+        let hq_container = MeshContainer::new(
+            hq_trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
+        let container = MeshContainer::new(trimesh, create_empty_material(), true, None).unwrap();
+        let threshold = OverlapThreshold::default()
+            .resolve(&container, &hq_container)
+            .unwrap();
+
+        let overlapping = container
+            .calc_overlapping_vertice_idxs(&hq_container, threshold, DistanceTest::Exact)
+            .unwrap();
+        let cuts = container
+            .calc_boundary_cuts(&overlapping, &hq_container, threshold, DistanceTest::Exact)
+            .unwrap();
+
+        assert!(!cuts.is_empty());
+
+        let vertices = positions_f32(&container.mesh).unwrap();
+        for cut in cuts {
+            let p_kept = vertices[cut.kept as usize];
+            let p_overlapping = vertices[cut.overlapping as usize];
+            let p_before = p_kept + (p_overlapping - p_kept) * (cut.t - 0.01);
+            let p_after = p_kept + (p_overlapping - p_kept) * (cut.t + 0.01);
+
+            assert!(
+                !vertex_overlapping(&p_before, &hq_container, threshold, DistanceTest::Exact)
+                    .unwrap()
+            );
+            assert!(
+                vertex_overlapping(&p_after, &hq_container, threshold, DistanceTest::Exact)
+                    .unwrap()
+            );
+        }
+    }
+
+    fn create_hq_triangle() -> MeshContainer {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+        MeshContainer::new(
+            trimesh,
+            create_empty_material(),
+            false,
+            Some(GridCellSize::default()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_beside_edge_outside_threshold() {
+        let container = create_hq_triangle();
+
+        // 0.085 from the hypotenuse, in the triangle plane. The lenient test
+        // accepts it, as it is within the expanded triangle.
+        let vertex = Vec3::new(0.56, 0.56, 0.0);
+
+        assert!(!vertex_overlapping(&vertex, &container, 0.05, DistanceTest::Exact).unwrap());
+        assert!(vertex_overlapping(&vertex, &container, 0.05, DistanceTest::Lenient).unwrap());
+    }
+
+    #[test]
+    fn test_beside_edge_inside_threshold() {
+        let container = create_hq_triangle();
+
+        // 0.5 below the edge along the x axis
+        let vertex = Vec3::new(0.5, -0.3, 0.4);
+
+        assert!(vertex_overlapping(&vertex, &container, 1.0, DistanceTest::Exact).unwrap());
+    }
+
+    #[test]
+    fn test_near_corner() {
+        let container = create_hq_triangle();
+
+        // Diagonally off the corner at the origin, 0.8 and 1.2 away
+        let inside = Vec3::new(-0.4, -0.4, -0.565_685);
+        let outside = Vec3::new(-0.6, -0.6, -0.848_528);
+
+        assert!(vertex_overlapping(&inside, &container, 1.0, DistanceTest::Exact).unwrap());
+        assert!(!vertex_overlapping(&outside, &container, 1.0, DistanceTest::Exact).unwrap());
+    }
+
+    #[test]
+    fn test_meshes_paired_with_materials_by_id() {
+        let dir = TempDir::new("material_ids");
+        std::fs::write(
+            dir.join("test.mtl"),
+            "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("test.obj"),
+            "mtllib test.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             g a\nf 1 3 2\n\
+             g b\nusemtl blue\nf 1 2 3\n\
+             g c\nusemtl blue\nf 2 3 1\n\
+             g d\nusemtl red\nf 3 1 2\n",
+        )
+        .unwrap();
+
+        let meshes = try_load_and_process_obj(dir.join("test.obj").as_os_str()).unwrap();
+
+        let names = meshes
+            .iter()
+            .map(|(_, material, _)| material.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(meshes.len(), 4);
+        assert_eq!(names, ["default", "blue", "blue", "red"]);
+
+        // A group name used again under another object keeps that object
+        std::fs::write(
+            dir.join("test.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             o a\ng default\nf 1 2 3\n\
+             o b\ng default\nf 1 2 3\n",
+        )
+        .unwrap();
+        let meshes = try_load_and_process_obj(dir.join("test.obj").as_os_str()).unwrap();
+        let objects = meshes
+            .iter()
+            .map(|(_, _, names)| names.object.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(objects, [Some("a"), Some("b")]);
+
+        // A material missing from the MTL file splits the group like any
+        // other change of material, and the names stay with the meshes
+        std::fs::write(
+            dir.join("test.obj"),
+            "mtllib test.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             g a\nusemtl red\nf 1 2 3\nusemtl missing\nf 2 3 1\n\
+             g b\nusemtl missing\nf 3 1 2\nusemtl blue\nf 1 3 2\n",
+        )
+        .unwrap();
+        let meshes = try_load_and_process_obj(dir.join("test.obj").as_os_str()).unwrap();
+        let pairs = meshes
+            .iter()
+            .map(|(_, material, names)| (material.name.as_str(), names.group.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            [
+                ("red", Some("a")),
+                ("default", Some("a")),
+                ("default", Some("b")),
+                ("blue", Some("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_meshes_without_triangles_skipped() {
+        let dir = TempDir::new("empty_meshes");
+        let path = dir.join("test.obj");
+        // tobj emits the last group even though it has no faces
+        std::fs::write(
+            &path,
+            "v 0 0 0\nv 3 0 0\nv 0 4 0\ng full\nf 1 2 3\ng empty\n",
+        )
+        .unwrap();
+
+        let model = Model::try_new_from_file(path.clone().into(), true, None, 1).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.mean_edge_length(), Some(4.0));
+
+        std::fs::write(&path, "v 0 0 0\nv 3 0 0\nv 0 4 0\ng empty\n").unwrap();
+        let error = Model::try_new_from_file(path.into(), true, None, 1).unwrap_err();
+        assert!(matches!(error, CleanerError::EmptyMesh));
+        let empty = TriMesh {
+            indices: Indices::U32(vec![]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            mean_edge_length(&empty),
+            Err(CleanerError::EmptyMesh)
+        ));
+    }
+
+    #[test]
+    fn test_delete_keeps_normals() {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2, 0, 2, 3]),
+            normals: Some((0..4).map(|i| Vec3::new(i as f32, 0.0, 1.0)).collect()),
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+
+        let mut container =
+            MeshContainer::new(trimesh, create_empty_material(), false, None).unwrap();
+        container.indices_to_delete.insert(1);
+        container.do_delete_vertices().unwrap();
+
+        assert_eq!(indices_u32(&container.mesh).unwrap(), [0, 1, 2]);
+        let normals = container.mesh.normals.unwrap();
+        assert_eq!(normals, [0.0, 2.0, 3.0].map(|x| Vec3::new(x, 0.0, 1.0)));
+    }
+
+    /// A quad and a separate triangle
+    fn create_quad_and_triangle() -> MeshContainer {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(5.0, 0.0, 0.0),
+                Vec3::new(6.0, 0.0, 0.0),
+                Vec3::new(6.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2, 0, 2, 3, 4, 5, 6]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+        MeshContainer::new(trimesh, create_empty_material(), false, None).unwrap()
+    }
+
+    #[test]
+    fn test_vertex_classes() {
+        use VertexClass::*;
+
+        let mut deleted = create_quad_and_triangle();
+        deleted.overlapping_vertice_idxs.insert(2);
+        deleted.mark_vertices_to_delete().unwrap();
+        assert_eq!(deleted.vertex_classes(CutMode::Vertices)[2], Deleted);
+
+        // Vertices 2 and 3 share a triangle with vertex 0, so they are kept
+        // to connect to it
+        let mut boundary = create_quad_and_triangle();
+        boundary.overlapping_vertice_idxs.extend([2, 3]);
+        boundary.mark_vertices_to_delete().unwrap();
+        assert_eq!(
+            boundary.vertex_classes(CutMode::Vertices),
+            [Kept, Kept, BoundaryKept, BoundaryKept, Kept, Kept, Kept]
+        );
+
+        // With 0 and 1 removed as an island of two, the whole quad goes
+        let mut container = create_quad_and_triangle();
+        container.overlapping_vertice_idxs.extend([2, 3]);
+        container
+            .mark_islands_as_overlapping(IslandThreshold {
+                mode: IslandMode::VertexCount,
+                value: 3.0,
+            })
+            .unwrap();
+        container.mark_vertices_to_delete().unwrap();
+        assert_eq!(
+            container.vertex_classes(CutMode::Vertices),
+            [Island, Island, Deleted, Deleted, Kept, Kept, Kept]
+        );
+        assert_eq!(
+            container.vertex_classes(CutMode::Clip),
+            [Island, Island, Overlapping, Overlapping, Kept, Kept, Kept]
+        );
+    }
+
+    #[test]
+    fn test_island_modes() {
+        // Islands {0, 1} without triangles of its own and {4, 5, 6} with an
+        // area of 0.5 and a diagonal of 1.41
+        let islands = |mode, value| {
+            let mut container = create_quad_and_triangle();
+            container.overlapping_vertice_idxs.extend([2, 3]);
+            container
+                .mark_islands_as_overlapping(IslandThreshold { mode, value })
+                .unwrap();
+            let mut island = container
+                .island_vertice_idxs
+                .into_iter()
+                .collect::<Vec<_>>();
+            island.sort();
+            island
+        };
+
+        assert_eq!(islands(IslandMode::VertexCount, 3.0), [0, 1]);
+        assert_eq!(islands(IslandMode::VertexCount, 4.0), [0, 1, 4, 5, 6]);
+        assert_eq!(islands(IslandMode::Area, 0.4), [0, 1]);
+        assert_eq!(islands(IslandMode::Area, 0.6), [0, 1, 4, 5, 6]);
+        assert_eq!(islands(IslandMode::Diagonal, 1.2), [0, 1]);
+        assert_eq!(islands(IslandMode::Diagonal, 1.5), [0, 1, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_recompute_normals() {
+        // Two triangles folded 90 degrees along the y axis. The fold
+        // vertices are duplicated as if they were on a uv seam.
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -2.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2, 3, 4, 5]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+
+        let mut container =
+            MeshContainer::new(trimesh, create_empty_material(), false, None).unwrap();
+
+        container
+            .recompute_normals(NormalMode::AngleWeighted)
+            .unwrap();
+        let normals = container.mesh.normals.clone().unwrap();
+        let fold = Vec3::new(1.0, 0.0, 1.0).normalize();
+        assert!((normals[0] - fold).magnitude() < 1e-5);
+        assert_eq!(normals[0], normals[3]);
+        assert!((normals[2] - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+
+        // The larger triangle dominates when weighting by area
+        container.recompute_normals(NormalMode::Smooth).unwrap();
+        let normals = container.mesh.normals.unwrap();
+        assert!(normals[0].x > normals[0].z);
+    }
+
+    #[test]
+    fn test_obj_names_by_statement() {
+        let expected = |object: Option<&str>, group: Option<&str>| MeshNames {
+            object: object.map(str::to_string),
+            group: group.map(str::to_string),
+        };
+        let dir = TempDir::new("obj_names");
+        let load_names = |obj: &str| {
+            let path = dir.join("test.obj");
+            std::fs::write(&path, format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{obj}")).unwrap();
+            try_load_and_process_obj(path.as_os_str())
+                .unwrap()
+                .into_iter()
+                .map(|(_, _, names)| names)
+                .collect::<Vec<_>>()
+        };
+
+        // The empty tile_1 object doesn't start a model of its own
+        let names = load_names(
+            "g loose\nf 1 2 3\no tile_1\ng roof\nf 1 2 3\ng walls\nf 1 2 3\no tile_2\ng roof 2\nf 1 2 3\n",
+        );
+        assert_eq!(
+            names,
+            [
+                expected(None, Some("loose")),
+                expected(Some("tile_1"), Some("roof")),
+                expected(Some("tile_1"), Some("walls")),
+                expected(Some("tile_2"), Some("roof 2")),
+            ]
+        );
+
+        // Faces before any statement have no names
+        let names = load_names("f 1 2 3\ng walls\nf 1 2 3\n");
+        assert_eq!(names, [expected(None, None), expected(None, Some("walls"))]);
+    }
+
+    #[test]
+    fn test_obj_names_with_material_change_in_group() {
+        let dir = TempDir::new("obj_names_usemtl");
+        std::fs::write(
+            dir.join("test.mtl"),
+            "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n",
+        )
+        .unwrap();
+        // The same group name under two objects, and a material change
+        // within a group
+        std::fs::write(
+            dir.join("test.obj"),
+            "mtllib test.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             o a\ng default\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\n\
+             o b\ng default\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let meshes = try_load_and_process_obj(dir.join("test.obj").as_os_str()).unwrap();
+
+        let meshes = meshes
+            .iter()
+            .map(|(_, material, names)| {
+                (
+                    material.name.as_str(),
+                    names.object.as_deref(),
+                    names.group.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            meshes,
+            [
+                ("red", Some("a"), Some("default")),
+                ("blue", Some("a"), Some("default")),
+                ("red", Some("b"), Some("default")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlap_threshold_modes() {
+        let triangle = |scale: f32| TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(scale, 0.0, 0.0),
+                Vec3::new(scale, scale, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2]),
+            normals: None,
+            tangents: None,
+            uvs: None,
+            colors: None,
+        };
+        let container =
+            MeshContainer::new(triangle(1.0), create_empty_material(), true, None).unwrap();
+        let hq_container = MeshContainer::new(
+            triangle(3.0),
+            create_empty_material(),
+            true,
+            Some(GridCellSize::default()),
+        )
+        .unwrap();
+        let mean_edge_len = (2.0 + 2.0_f32.sqrt()) / 3.0;
+
+        let resolve = |mode, value| {
+            OverlapThreshold { mode, value }
+                .resolve(&container, &hq_container)
+                .unwrap()
+        };
+
+        assert_eq!(resolve(ThresholdMode::Absolute, 0.5), 0.5);
+        assert!((resolve(ThresholdMode::MeanEdge, 2.0) - 2.0 * mean_edge_len).abs() < 1e-5);
+        assert!((resolve(ThresholdMode::HqEdge, 2.0) - 6.0 * mean_edge_len).abs() < 1e-5);
+
+        // Without mean edge length or index grid the asset fails instead of
+        // the run
+        let bare = MeshContainer::new(triangle(1.0), create_empty_material(), false, None).unwrap();
+        assert!(matches!(
+            OverlapThreshold::default().resolve(&bare, &hq_container),
+            Err(CleanerError::MissingMeshData(_))
+        ));
+        assert!(matches!(
+            hq_container.calc_overlapping_vertice_idxs(&bare, 1.0, DistanceTest::Exact),
+            Err(CleanerError::MissingMeshData(_))
+        ));
+    }
+
+    #[test]
+    fn test_clip_interpolates_cut_vertices() {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2, 0, 2, 3]),
+            normals: None,
+            tangents: None,
+            uvs: Some(vec![
+                Vector2::new(0.0, 0.0),
+                Vector2::new(1.0, 0.0),
+                Vector2::new(1.0, 1.0),
+                Vector2::new(0.0, 1.0),
+            ]),
+            colors: None,
+        };
+
+        let mut container =
+            MeshContainer::new(trimesh, create_empty_material(), false, None).unwrap();
+        container.overlapping_vertice_idxs.insert(2);
+        container.add_boundary_cuts(&[
+            BoundaryCut {
+                kept: 1,
+                overlapping: 2,
+                t: 0.5,
+            },
+            BoundaryCut {
+                kept: 0,
+                overlapping: 2,
+                t: 0.5,
+            },
+            BoundaryCut {
+                kept: 3,
+                overlapping: 2,
+                t: 0.5,
+            },
+        ]);
+
+        container.do_clip_vertices().unwrap();
+
+        // Three kept vertices and one shared vertex for each cut edge
+        let vertices = positions_f32(&container.mesh).unwrap();
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(container.mesh.triangle_count(), 4);
+        assert!(vertices.contains(&Vec3::new(1.0, 0.5, 0.0)));
+        assert!(vertices.contains(&Vec3::new(0.5, 0.5, 0.0)));
+        assert!(vertices.contains(&Vec3::new(0.5, 1.0, 0.0)));
+
+        let uvs = container.mesh.uvs.as_ref().unwrap();
+        for (vertex, uv) in vertices.iter().zip(uvs.iter()) {
+            assert_eq!(vertex.x, uv.x);
+            assert_eq!(vertex.y, uv.y);
+        }
+    }
+
+    #[test]
+    fn test_stitch_seams_bridges_to_hq_boundary() {
+        let trimesh = TriMesh {
+            positions: Positions::F32(vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ]),
+            indices: Indices::U32(vec![0, 1, 2, 0, 2, 3]),
+            normals: None,
+            tangents: None,
+            uvs: Some(vec![
+                Vector2::new(0.0, 0.0),
+                Vector2::new(1.0, 0.0),
+                Vector2::new(1.0, 1.0),
+                Vector2::new(0.0, 1.0),
+            ]),
+            colors: None,
+        };
+
+        let mut container =
+            MeshContainer::new(trimesh, create_empty_material(), true, None).unwrap();
+        container.overlapping_vertice_idxs.insert(2);
+        container.add_boundary_cuts(&[
+            BoundaryCut {
+                kept: 1,
+                overlapping: 2,
+                t: 0.5,
+            },
+            BoundaryCut {
+                kept: 0,
+                overlapping: 2,
+                t: 0.5,
+            },
+            BoundaryCut {
+                kept: 3,
+                overlapping: 2,
+                t: 0.5,
+            },
+        ]);
+
+        container.hq_boundary_segments = vec![[Vec3::new(0.6, 1.2, 0.0), Vec3::new(1.2, 0.6, 0.0)]];
+        container.max_overlap_threshold = 1.0;
+
+        container.record_boundary().unwrap();
+        container.do_clip_vertices().unwrap();
+        container.stitch_seams().unwrap();
+
+        // Two seam edges, both bridged with two triangles
+        assert_eq!(container.mesh.triangle_count(), 8);
+        assert_eq!(container.mesh.vertex_count(), 9);
+        let vertices = positions_f32(&container.mesh).unwrap();
+        assert!(
+            vertices
+                .iter()
+                .any(|v| v.distance(Vec3::new(0.9, 0.9, 0.0)) < 1e-5)
+        );
+
+        // Bridging triangles have consistent winding with the rest
+        let indices = indices_u32(&container.mesh).unwrap();
+        let mut directed_edges = HashSet::new();
+        for tri in indices.chunks_exact(3) {
+            for i in 0..3 {
+                assert!(directed_edges.insert((tri[i], tri[(i + 1) % 3])));
+            }
+        }
+    }
 }
@@ -0,0 +1,252 @@
+use std::{ffi::OsString, fs::File, io::BufWriter, path::Path, time::Duration};
+
+use clap::ValueEnum;
+use serde_json::{Value, json};
+
+use crate::{
+    config::Config,
+    error::{AssetFailure, CleanerError},
+};
+
+/// Whether an asset was cleaned or used for cleaning
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssetRole {
+    Hq,
+    #[default]
+    Normal,
+}
+
+impl AssetRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hq => "hq",
+            Self::Normal => "normal",
+        }
+    }
+}
+
+/// How an asset ended up in the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AssetOutcome {
+    /// Copied from the source file via a [`crate::ModelReference`]
+    #[default]
+    Copied,
+    /// Written from the cleaned geometry
+    Rewritten,
+    /// Fully overlapping and left out of the output
+    Deleted,
+}
+
+impl AssetOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Copied => "copied",
+            Self::Rewritten => "rewritten",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// What the run did to a single asset.
+#[derive(Debug, Default, Clone)]
+pub struct AssetSummary {
+    pub path: OsString,
+    pub role: AssetRole,
+    /// Index of the asset layer, 0 for the top layer
+    pub layer: usize,
+    pub outcome: AssetOutcome,
+    pub vertices_before: usize,
+    pub triangles_before: usize,
+    /// Vertex count of the output, including the vertices added by clipping
+    /// and stitching
+    pub vertices_after: usize,
+    pub triangles_after: usize,
+    /// Names of the meshes that were fully overlapping and dropped whole
+    pub dropped_meshes: Vec<String>,
+    /// For normal assets the hq assets that caused the removals, for hq
+    /// assets the normal assets they removed geometry from. Hq assets
+    /// cleaned against each other also list the hq assets that caused their
+    /// removals. Sorted.
+    pub overlapping_assets: Vec<OsString>,
+    /// Smallest and largest resolved overlap threshold of the mesh pairs
+    /// with overlaps
+    pub threshold_range: Option<(f32, f32)>,
+    /// Time spent loading the source file
+    pub load_time: Duration,
+    /// Time spent on overlaps: finding them for hq assets, removing them for
+    /// normal assets
+    pub process_time: Duration,
+    /// Time spent writing the output, zero for dry runs and deleted assets
+    pub write_time: Duration,
+}
+
+impl AssetSummary {
+    /// Net number of removed vertices
+    pub fn deleted_vertices(&self) -> usize {
+        self.vertices_before.saturating_sub(self.vertices_after)
+    }
+
+    /// Net number of removed triangles
+    pub fn deleted_triangles(&self) -> usize {
+        self.triangles_before.saturating_sub(self.triangles_after)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.to_string_lossy(),
+            "role": self.role.as_str(),
+            "layer": self.layer,
+            "outcome": self.outcome.as_str(),
+            "vertices_before": self.vertices_before,
+            "vertices_after": self.vertices_after,
+            "triangles_before": self.triangles_before,
+            "triangles_after": self.triangles_after,
+            "dropped_meshes": self.dropped_meshes,
+            "overlapping_assets": self
+                .overlapping_assets
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>(),
+            "threshold": self.threshold_range.map(|(min, max)| json!({ "min": min, "max": max })),
+            "timings_ms": {
+                "load": millis(self.load_time),
+                "process": millis(self.process_time),
+                "write": millis(self.write_time),
+            },
+        })
+    }
+}
+
+/// Summary of a cleaning run.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of hq assets loaded, the assets of the top layer
+    pub hq_asset_cnt: usize,
+    /// Number of normal assets loaded, the assets of all other layers
+    pub normal_asset_cnt: usize,
+    /// Cleaned assets that had no overlaps and were copied as-is. Hq assets
+    /// are only cleaned with [`Config::hq_tie_break`].
+    pub copied_assets: Vec<OsString>,
+    /// Cleaned assets that had overlapping geometry removed
+    pub rewritten_assets: Vec<OsString>,
+    /// Cleaned assets that were fully overlapping and left out of the output
+    pub deleted_assets: Vec<OsString>,
+    /// Details of each processed asset, sorted by layer and path
+    pub asset_summaries: Vec<AssetSummary>,
+    /// Assets that failed to load, process or write. Assets that failed
+    /// processing are left out of the output.
+    pub failed_assets: Vec<AssetFailure>,
+    /// Wall clock duration of the run
+    pub duration: Duration,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+impl Report {
+    /// The report together with the settings of `config` as JSON
+    pub fn to_json(&self, config: &Config) -> Value {
+        json!({
+            "settings": {
+                "overlap_threshold": {
+                    "mode": value_name(&config.overlap_threshold.mode),
+                    "value": config.overlap_threshold.value,
+                },
+                "distance_test": value_name(&config.distance_test),
+                "island_threshold": {
+                    "mode": value_name(&config.island_threshold.mode),
+                    "value": config.island_threshold.value,
+                },
+                "cut_mode": value_name(&config.cut_mode),
+                "texture_downscale": {
+                    "hq": config.hq_texture_downscale,
+                    "normal": config.normal_texture_downscale,
+                    "overrides": config
+                        .texture_downscale_overrides
+                        .iter()
+                        .map(|(folder, factor)| json!({ "folder": folder.to_string_lossy(), "factor": factor }))
+                        .collect::<Vec<_>>(),
+                },
+                "texture_format": value_name(&config.texture_format),
+                "jpeg_quality": config.jpeg_quality,
+                "crop_textures": config.crop_textures,
+                "hq_tie_break": config.hq_tie_break.map(|tie_break| value_name(&tie_break)),
+                "layers": config
+                    .asset_layers()
+                    .iter()
+                    .map(|layer| json!({
+                        "folders": layer.folders.iter().map(|f| f.to_string_lossy()).collect::<Vec<_>>(),
+                        "overlap_threshold": {
+                            "mode": value_name(&layer.overlap_threshold.mode),
+                            "value": layer.overlap_threshold.value,
+                        },
+                        "texture_downscale": layer.texture_downscale,
+                    }))
+                    .collect::<Vec<_>>(),
+                "grid_cell_size": {
+                    "mode": value_name(&config.grid_cell_size.mode),
+                    "value": config.grid_cell_size.value,
+                },
+            },
+            "hq_asset_count": self.hq_asset_cnt,
+            "normal_asset_count": self.normal_asset_cnt,
+            "duration_ms": millis(self.duration),
+            "assets": self
+                .asset_summaries
+                .iter()
+                .map(AssetSummary::to_json)
+                .collect::<Vec<_>>(),
+            "failed_assets": self
+                .failed_assets
+                .iter()
+                .map(|f| json!({ "path": f.path.to_string_lossy(), "error": f.error.to_string() }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Writes [`Self::to_json`] to dest
+    pub fn write_json(&self, dest: &Path, config: &Config) -> Result<(), CleanerError> {
+        let file = File::create(dest).map_err(|e| CleanerError::io(dest, e))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.to_json(config))
+            .map_err(|e| CleanerError::io(dest, e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_summary_json() {
+        let summary = AssetSummary {
+            path: "normal/a.obj".into(),
+            outcome: AssetOutcome::Rewritten,
+            layer: 2,
+            vertices_before: 10,
+            vertices_after: 4,
+            overlapping_assets: vec!["hq/b.obj".into()],
+            threshold_range: Some((0.5, 2.0)),
+            ..Default::default()
+        };
+
+        let json = summary.to_json();
+        assert_eq!(json["role"], "normal");
+        assert_eq!(json["outcome"], "rewritten");
+        assert_eq!(json["layer"], 2);
+        assert_eq!(json["vertices_after"], 4);
+        assert_eq!(json["overlapping_assets"][0], "hq/b.obj");
+        assert_eq!(json["threshold"]["max"], 2.0);
+        assert_eq!(summary.deleted_vertices(), 6);
+
+        let json = AssetSummary::default().to_json();
+        assert!(json["threshold"].is_null());
+    }
+}
@@ -0,0 +1,86 @@
+//! Helpers shared by the unit tests
+
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    config::Config,
+    model::{OverlapThreshold, ThresholdMode},
+};
+
+/// Directory under the system temp dir that is removed again when dropped,
+/// also when the test panics
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory. `name` only makes it easier to tell
+    /// which test left it behind.
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "obj-overlap-cleaner_{name}_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Config cleaning the assets of `dir/normal` against those of `dir/hq` to
+/// `dir/out`, with the defaults of the command line except for an absolute
+/// overlap threshold of 0.1 and the normal asset textures copied as they are
+pub(crate) fn test_config(dir: &Path) -> Config {
+    Config {
+        hq_asset_folders: vec![dir.join("hq").into()],
+        normal_asset_folder: dir.join("normal").into(),
+        out_folder: dir.join("out").into(),
+        overlap_threshold: OverlapThreshold {
+            mode: ThresholdMode::Absolute,
+            value: 0.1,
+        },
+        normal_texture_downscale: 1,
+        ..Config::default()
+    }
+}
+
+/// Writes an OBJ file with a group of triangles for each of `groups`, given
+/// as the corners of the triangles
+pub(crate) fn write_obj(path: &Path, groups: &[(&str, &[[f32; 3]])]) {
+    let mut obj = String::new();
+    let mut vertex_count = 0;
+    for (name, corners) in groups {
+        obj += &format!("g {name}\n");
+        for [x, y, z] in corners.iter() {
+            obj += &format!("v {x} {y} {z}\n");
+        }
+        for _ in 0..corners.len() / 3 {
+            obj += &format!(
+                "f {} {} {}\n",
+                vertex_count + 1,
+                vertex_count + 2,
+                vertex_count + 3
+            );
+            vertex_count += 3;
+        }
+    }
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, obj).unwrap();
+}
@@ -1,96 +1,520 @@
 use std::{
+    collections::{HashMap, HashSet},
     ffi::OsString,
+    path::PathBuf,
     sync::{Arc, Mutex, RwLock, mpsc},
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+use three_d_asset::Vec3;
+
 use crate::{
-    io::WriteToFolder,
-    model::{Model, ModelReference, OutAsset},
+    config::{AssetLayer, Config},
+    error::{AssetFailure, CleanerError},
+    grid::GridCellSize,
+    io::{AssetFilter, WriteOptions, WriteToFolder, plan_out_paths, plan_textures},
+    model::{BoundaryCut, CutMode, Model, ModelReference, OutAsset, OverlapThreshold, expand_aabb},
+    report::{AssetOutcome, AssetRole, AssetSummary, Report},
 };
 
 pub struct WorldAssets {
     pub hq_asset_files: Vec<OsString>,
     pub normal_assets: Arc<Vec<Arc<RwLock<Model>>>>,
+    /// Layers of the run with the asset files found in them
+    layers: Vec<(AssetLayer, Vec<OsString>)>,
+    /// Index of the layer the normal assets are from. It is only 0, the top
+    /// layer, while the top layer is cleaned against itself with
+    /// [`Config::hq_tie_break`].
+    layer: usize,
+    /// Rank of each top layer asset while it is cleaned against itself
+    hq_ranks: Option<HashMap<OsString, usize>>,
     out_assets: Vec<OutAsset>,
+    /// Output path of each asset relative to the out folder, by source file
+    out_paths: HashMap<OsString, PathBuf>,
     num_threads: usize,
+    config: Arc<Config>,
+    report: Report,
 }
 
-fn hq_asset_worker(
-    hq_asset_files: Arc<Mutex<Vec<OsString>>>,
-    normal_assets: Arc<Vec<Arc<RwLock<Model>>>>,
-    write_hq_asset_ref: Arc<Mutex<Vec<ModelReference>>>,
-) {
-    loop {
-        let mut files = hq_asset_files.lock().unwrap();
+/// Which of two overlapping hq assets keeps the overlapping geometry when the
+/// hq assets are cleaned against each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HqTieBreak {
+    /// Assets of earlier hq folders win, and within a folder the earlier
+    /// files
+    #[default]
+    FolderOrder,
+    /// Assets with the shorter mean edge length win
+    MeshDensity,
+    /// Assets with the later capture date win. The date is read from the
+    /// `capture_date` field of a JSON sidecar named after the asset file,
+    /// e.g. `tile.obj.json`, as `YYYY-MM-DD` with an optional
+    /// `THH:MM:SS`. Assets without a valid date lose.
+    CaptureDate,
+}
 
-        let hq_asset_path = match files.pop() {
-            Some(asset) => asset,
-            None => return,
-        };
+/// Ranks the assets of the top layer for cleaning them against each other,
+/// 0 for the asset that wins all of its overlaps. The files are given in
+/// folder order, which ties keep.
+fn rank_hq_assets(
+    files: &[OsString],
+    models: &[Arc<RwLock<Model>>],
+    tie_break: HqTieBreak,
+) -> HashMap<OsString, usize> {
+    let mut order = files.to_vec();
+    match tie_break {
+        HqTieBreak::FolderOrder => {}
+        HqTieBreak::MeshDensity => {
+            let edge_lengths = models
+                .iter()
+                .map(|model| {
+                    let model = model.read().unwrap();
+                    let edge_length = model.mean_edge_length().unwrap_or(f32::INFINITY);
+                    (model.source_file.clone(), edge_length)
+                })
+                .collect::<HashMap<_, _>>();
+            let edge_length = |file: &OsString| *edge_lengths.get(file).unwrap_or(&f32::INFINITY);
+            order.sort_by(|a, b| edge_length(a).total_cmp(&edge_length(b)));
+        }
+        HqTieBreak::CaptureDate => {
+            let dates = files
+                .iter()
+                .map(|file| (file.clone(), crate::io::read_capture_date(file)))
+                .collect::<HashMap<_, _>>();
+            order.sort_by(|a, b| dates[b].cmp(&dates[a]));
+        }
+    }
 
-        drop(files);
+    order
+        .into_iter()
+        .enumerate()
+        .map(|(rank, file)| (file, rank))
+        .collect()
+}
 
-        let hq_asset = Model::try_new_from_file(hq_asset_path.clone(), false, true, 1).unwrap();
+/// Asset of the layers above the current one, which the normal assets are
+/// cleaned against. Each is given index grids by the worker processing it,
+/// so that only the assets being processed have them.
+enum HqSource {
+    /// Asset file loaded as it is, with its texture downscale factor
+    File(OsString, u32),
+    /// Asset as it was cleaned in its own layer, the way it is written
+    Cleaned(Box<Model>),
+    /// Normal asset at an index, loaded with index grids, while the top
+    /// layer is cleaned against itself
+    Normal(usize),
+}
 
-        let hq_asset_name = hq_asset.source_file.clone();
-        let start_time = Instant::now();
+/// Which asset pairs a pass of [`WorldAssets::process_overlaps`] tests and
+/// how
+struct OverlapPass {
+    threshold: OverlapThreshold,
+    /// Rank of each asset when the top layer is cleaned against itself.
+    /// Normal assets are then only tested against the hq assets ranked
+    /// above them.
+    ranks: Option<HashMap<OsString, usize>>,
+}
+
+/// Overlaps of a single normal mesh with an hq asset
+#[derive(Default)]
+struct MeshOverlaps {
+    vertice_idxs: Vec<usize>,
+    boundary_cuts: Vec<BoundaryCut>,
+    hq_boundary_segments: Vec<[Vec3; 2]>,
+    /// Smallest resolved threshold of the hq meshes with overlaps
+    min_threshold: f32,
+    /// Largest resolved threshold of the hq meshes with overlaps
+    max_threshold: f32,
+}
+
+/// Calculates the overlaps for each mesh in normal_asset
+fn calc_overlaps(
+    normal_asset: &Model,
+    hq_asset: &Model,
+    threshold: &OverlapThreshold,
+    config: &Config,
+) -> Result<Vec<MeshOverlaps>, CleanerError> {
+    let mut overlaps: Vec<MeshOverlaps> = vec![];
+
+    // Cleaned hq assets can have meshes that lost all their triangles
+    let hq_meshes = hq_asset
+        .meshes
+        .iter()
+        .filter(|hq_mesh| hq_mesh.mesh.triangle_count() > 0)
+        .collect::<Vec<_>>();
+
+    // Resolved threshold for each normal mesh and hq mesh pair
+    let thresholds = normal_asset
+        .meshes
+        .iter()
+        .map(|mesh| {
+            hq_meshes
+                .iter()
+                .map(|hq_mesh| threshold.resolve(mesh, hq_mesh))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let min_threshold = thresholds
+        .iter()
+        .flatten()
+        .fold(f32::INFINITY, |a, b| a.min(*b));
+    let max_threshold = thresholds.iter().flatten().fold(0.0_f32, |a, b| a.max(*b));
+
+    if normal_asset
+        .aabb
+        .intersection(expand_aabb(hq_asset.aabb, max_threshold))
+        .is_some()
+    {
+        for (mesh, mesh_thresholds) in normal_asset.meshes.iter().zip(thresholds.iter()) {
+            let mut mesh_overlaps = MeshOverlaps::default();
+
+            for (hq_mesh, threshold) in hq_meshes.iter().zip(mesh_thresholds.iter()) {
+                let threshold = *threshold;
+                let vertice_idxs =
+                    mesh.calc_overlapping_vertice_idxs(hq_mesh, threshold, config.distance_test)?;
+                if vertice_idxs.is_empty() {
+                    continue;
+                }
+
+                if config.cut_mode == CutMode::Clip {
+                    mesh_overlaps.boundary_cuts.extend(mesh.calc_boundary_cuts(
+                        &vertice_idxs,
+                        hq_mesh,
+                        threshold,
+                        config.distance_test,
+                    )?);
+                }
+                if config.stitch_seams {
+                    mesh_overlaps
+                        .hq_boundary_segments
+                        .extend(mesh.calc_hq_boundary_segments(hq_mesh, threshold)?);
+                }
+                mesh_overlaps.min_threshold = match mesh_overlaps.vertice_idxs.is_empty() {
+                    true => threshold,
+                    false => mesh_overlaps.min_threshold.min(threshold),
+                };
+                mesh_overlaps.vertice_idxs.extend(vertice_idxs);
+                mesh_overlaps.max_threshold = mesh_overlaps.max_threshold.max(threshold);
+            }
+            overlaps.push(mesh_overlaps);
+        }
 
+        if min_threshold <= max_threshold {
+            println!(
+                "Overlap threshold for {:?} against {:?}: {min_threshold}..{max_threshold}",
+                normal_asset.source_file, hq_asset.source_file
+            );
+        }
+    }
+
+    Ok(overlaps)
+}
+
+/// Overlaps of the normal asset at an index with an hq asset, found while
+/// the hq assets are the normal assets themselves
+type FoundOverlaps = (usize, OsString, Vec<MeshOverlaps>);
+
+/// Records the overlaps of asset with the hq asset named hq_asset_name
+fn add_overlaps(asset: &mut Model, hq_asset_name: &OsString, overlaps: &[MeshOverlaps]) {
+    asset.overlapping_assets.insert(hq_asset_name.clone());
+    for (idx, overlap) in overlaps.iter().enumerate() {
+        if !overlap.vertice_idxs.is_empty() {
+            asset.threshold_range = Some(match asset.threshold_range {
+                Some((min, max)) => (
+                    min.min(overlap.min_threshold),
+                    max.max(overlap.max_threshold),
+                ),
+                None => (overlap.min_threshold, overlap.max_threshold),
+            });
+        }
+        let mesh = &mut asset.meshes[idx];
+        mesh.overlapping_vertice_idxs
+            .extend(overlap.vertice_idxs.iter());
+        mesh.add_boundary_cuts(&overlap.boundary_cuts);
+        mesh.hq_boundary_segments
+            .extend_from_slice(&overlap.hq_boundary_segments);
+        mesh.max_overlap_threshold = mesh.max_overlap_threshold.max(overlap.max_threshold);
+    }
+}
+
+/// Finds the overlaps of the normal assets with hq_asset and returns its
+/// summary. The overlaps are recorded on the normal assets, or collected to
+/// found when given, so that the normal assets are only read.
+fn process_hq_asset(
+    hq_asset: &Model,
+    normal_assets: &[Arc<RwLock<Model>>],
+    found: Option<&Mutex<Vec<FoundOverlaps>>>,
+    failures: &Mutex<Vec<AssetFailure>>,
+    pass: &OverlapPass,
+    config: &Config,
+) -> AssetSummary {
+    let hq_asset_name = hq_asset.source_file.clone();
+
+    for grid in hq_asset.meshes.iter().filter_map(|m| m.index_grid()) {
+        let stats = grid.stats();
         println!(
-            "Starting to process hq-asset {:?} against normal assets.",
-            hq_asset_name
+            "Index grid of {:?}: cell size {}, {} occupied of {} cells ({:.1} %), {:.1} triangles per cell (max {})",
+            hq_asset_name,
+            stats.cell_size,
+            stats.occupied_cells,
+            stats.bounding_cells,
+            100.0 * stats.occupancy(),
+            stats.mean_cell_triangles(),
+            stats.max_cell_triangles
         );
+    }
+    let start_time = Instant::now();
+    let mut overlapped_assets = vec![];
 
-        for normal_asset in normal_assets.iter() {
-            let asset_clone = normal_asset.clone();
-            let asset_read = asset_clone.read().unwrap();
+    println!(
+        "Starting to process hq-asset {:?} against normal assets.",
+        hq_asset_name
+    );
 
-            let mut overlaps: Vec<Vec<usize>> = vec![];
+    for (normal_idx, normal_asset) in normal_assets.iter().enumerate() {
+        let asset_read = normal_asset.read().unwrap();
 
-            if asset_read.aabb.intersection(hq_asset.aabb).is_some() {
-                for mesh in asset_read.meshes.iter() {
-                    let mut mesh_overlaps = vec![];
+        if let Some(ranks) = &pass.ranks
+            && ranks.get(&hq_asset_name) >= ranks.get(&asset_read.source_file)
+        {
+            continue;
+        }
 
-                    for hq_mesh in hq_asset.meshes.iter() {
-                        mesh_overlaps
-                            .extend_from_slice(&mesh.calc_overlapping_vertice_idxs(hq_mesh));
-                    }
-                    overlaps.push(mesh_overlaps);
-                }
+        let overlaps = match calc_overlaps(&asset_read, hq_asset, &pass.threshold, config) {
+            Ok(overlaps) => overlaps,
+            Err(error) => {
+                println!(
+                    "Failed processing {:?} against hq-asset {:?}: {error}",
+                    asset_read.source_file, hq_asset_name
+                );
+                failures.lock().unwrap().push(AssetFailure {
+                    path: asset_read.source_file.clone(),
+                    error,
+                });
+                continue;
             }
+        };
 
-            let no_overlaps = overlaps.is_empty() || overlaps.iter().all(|o| o.is_empty());
+        let no_overlaps = overlaps.is_empty() || overlaps.iter().all(|o| o.vertice_idxs.is_empty());
+        if no_overlaps {
+            continue;
+        }
 
-            if !no_overlaps {
+        overlapped_assets.push(asset_read.source_file.clone());
+        match found {
+            Some(found) => {
+                found
+                    .lock()
+                    .unwrap()
+                    .push((normal_idx, hq_asset_name.clone(), overlaps));
+            }
+            None => {
                 drop(asset_read);
-                let mut asset_write = asset_clone.write().unwrap();
-                for (idx, overlap) in overlaps.iter().enumerate() {
-                    asset_write.meshes[idx]
-                        .overlapping_vertice_idxs
-                        .extend(overlap);
+                add_overlaps(
+                    &mut normal_asset.write().unwrap(),
+                    &hq_asset_name,
+                    &overlaps,
+                );
+            }
+        }
+    }
+
+    let process_time = Instant::now() - start_time;
+
+    println!(
+        "Processed hq-asset: {:?} in {} ms",
+        hq_asset_name,
+        process_time.as_millis()
+    );
+
+    overlapped_assets.sort();
+    AssetSummary {
+        path: hq_asset_name,
+        role: AssetRole::Hq,
+        outcome: AssetOutcome::Copied,
+        vertices_before: hq_asset.vertex_count(),
+        triangles_before: hq_asset.triangle_count(),
+        vertices_after: hq_asset.vertex_count(),
+        triangles_after: hq_asset.triangle_count(),
+        overlapping_assets: overlapped_assets,
+        load_time: hq_asset.load_time,
+        process_time,
+        ..Default::default()
+    }
+}
+
+/// Finds the overlaps of the normal assets with the hq assets. Cleaned hq
+/// assets are handed back through cleaned_assets once processed. When the
+/// hq assets are the normal assets, the overlaps are collected to found.
+#[allow(clippy::too_many_arguments)]
+fn hq_asset_worker(
+    hq_sources: Arc<Mutex<Vec<HqSource>>>,
+    normal_assets: Arc<Vec<Arc<RwLock<Model>>>>,
+    write_hq_asset_ref: Arc<Mutex<Vec<ModelReference>>>,
+    cleaned_assets: Arc<Mutex<Vec<Model>>>,
+    found: Arc<Mutex<Vec<FoundOverlaps>>>,
+    summaries: Arc<Mutex<Vec<AssetSummary>>>,
+    failures: Arc<Mutex<Vec<AssetFailure>>>,
+    pass: Arc<OverlapPass>,
+    config: Arc<Config>,
+) {
+    loop {
+        let mut queue = hq_sources.lock().unwrap();
+
+        let hq_source = match queue.pop() {
+            Some(source) => source,
+            None => return,
+        };
+
+        drop(queue);
+
+        match hq_source {
+            HqSource::File(path, texture_downscale_factor) => {
+                let hq_asset = match Model::try_new_from_file(
+                    path.clone(),
+                    true,
+                    Some(config.grid_cell_size),
+                    texture_downscale_factor,
+                ) {
+                    Ok(hq_asset) => hq_asset,
+                    Err(error) => {
+                        println!("Failed loading hq-asset {path:?}: {error}");
+                        failures.lock().unwrap().push(AssetFailure { path, error });
+                        continue;
+                    }
+                };
+
+                let summary =
+                    process_hq_asset(&hq_asset, &normal_assets, None, &failures, &pass, &config);
+                summaries.lock().unwrap().push(summary);
+
+                let factor = hq_asset.texture_downscale_factor;
+                let hq_asset_ref = ModelReference::from_model(&hq_asset, factor);
+                write_hq_asset_ref.lock().unwrap().push(hq_asset_ref);
+            }
+            HqSource::Cleaned(mut hq_asset) => {
+                match hq_asset.build_index_grids(config.grid_cell_size) {
+                    Ok(()) => {
+                        let summary = process_hq_asset(
+                            &hq_asset,
+                            &normal_assets,
+                            None,
+                            &failures,
+                            &pass,
+                            &config,
+                        );
+                        summaries.lock().unwrap().push(summary);
+                    }
+                    Err(error) => {
+                        println!(
+                            "Failed building index grids for hq-asset {:?}: {error}",
+                            hq_asset.source_file
+                        );
+                        failures.lock().unwrap().push(AssetFailure {
+                            path: hq_asset.source_file.clone(),
+                            error,
+                        });
+                    }
                 }
+                hq_asset.drop_index_grids();
+                cleaned_assets.lock().unwrap().push(*hq_asset);
+            }
+            HqSource::Normal(idx) => {
+                let hq_asset = normal_assets[idx].read().unwrap();
+                let summary = process_hq_asset(
+                    &hq_asset,
+                    &normal_assets,
+                    Some(&found),
+                    &failures,
+                    &pass,
+                    &config,
+                );
+                summaries.lock().unwrap().push(summary);
             }
         }
+    }
+}
 
-        let duration = (Instant::now() - start_time).as_millis();
+/// Returns the out asset for model, or None if the model is totally
+/// overlapping, together with a summary of the changes
+fn mark_and_delete_model_vertices(
+    mut model: Model,
+    config: &Config,
+) -> Result<(Option<OutAsset>, AssetSummary), CleanerError> {
+    let mut summary = AssetSummary {
+        path: model.source_file.clone(),
+        vertices_before: model.vertex_count(),
+        triangles_before: model.triangle_count(),
+        overlapping_assets: model.overlapping_assets.iter().cloned().collect(),
+        threshold_range: model.threshold_range,
+        load_time: model.load_time,
+        ..Default::default()
+    };
+
+    // Islands first, so that they are deleted with the overlapping vertices
+    model.mark_islands_as_overlapping(config.island_threshold)?;
+    model.mark_vertices_to_delete()?;
 
-        println!("Processed hq-asset: {:?} in {} ms", hq_asset_name, duration);
+    if let Some(debug_folder) = &config.debug_folder {
+        crate::io::write_debug_obj(debug_folder, &model, config.cut_mode)?;
+    }
+
+    summary.dropped_meshes = model
+        .meshes
+        .iter()
+        .filter(|m| m.to_be_deleted())
+        .map(|m| m.display_name().to_string())
+        .collect();
+
+    if model.to_be_deleted() {
+        summary.outcome = AssetOutcome::Deleted;
+        return Ok((None, summary));
+    }
 
-        let hq_asset_ref = ModelReference::from_model(hq_asset, 1);
-        let mut write_hq_asset_ref_lock = write_hq_asset_ref.lock().unwrap();
-        write_hq_asset_ref_lock.push(hq_asset_ref);
+    if !model.modified() {
+        summary.outcome = AssetOutcome::Copied;
+        summary.vertices_after = summary.vertices_before;
+        summary.triangles_after = summary.triangles_before;
+        let factor = model.texture_downscale_factor;
+        let model_ref = ModelReference::from_model(&model, factor);
+        return Ok((Some(OutAsset::AssetRef(model_ref)), summary));
     }
+
+    if config.stitch_seams {
+        model.record_boundaries()?;
+    }
+
+    model.do_cut(config.cut_mode)?;
+
+    if config.stitch_seams {
+        model.stitch_seams()?;
+    }
+
+    model.recompute_normals(config.normal_mode)?;
+
+    if config.crop_textures && !config.dry_run {
+        crate::io::crop_textures(&mut model)?;
+    }
+
+    summary.outcome = AssetOutcome::Rewritten;
+    summary.vertices_after = model.vertex_count();
+    summary.triangles_after = model.triangle_count();
+
+    Ok((Some(OutAsset::Asset(model)), summary))
 }
 
 fn mark_and_delete_vertices_worker(
     assets: Arc<Mutex<Vec<Model>>>,
     results: Arc<Mutex<Vec<OutAsset>>>,
+    summaries: Arc<Mutex<Vec<AssetSummary>>>,
+    failures: Arc<Mutex<Vec<AssetFailure>>>,
+    config: Arc<Config>,
 ) {
     loop {
         let mut assets_lock = assets.lock().unwrap();
 
-        let mut model = match assets_lock.pop() {
+        let model = match assets_lock.pop() {
             Some(model) => model,
             None => return,
         };
@@ -101,25 +525,28 @@ fn mark_and_delete_vertices_worker(
         let model_file = model.source_file.clone();
         println!("Deleting overlapping vertices for {:?}", model_file);
 
-        model.mark_vertices_to_delete();
-        model.mark_islands_as_overlapping(15);
-
-        if model.to_be_deleted() {
-            continue;
-        }
-
-        if !model.modified() {
-            let model_ref = ModelReference::from_model(model, 2);
-            let mut results_lock = results.lock().unwrap();
-            results_lock.push(OutAsset::AssetRef(model_ref));
-            continue;
+        match mark_and_delete_model_vertices(model, &config) {
+            Ok((out_asset, mut summary)) => {
+                summary.process_time = Instant::now() - start_time;
+                summaries.lock().unwrap().push(summary);
+                match out_asset {
+                    Some(out_asset) => results.lock().unwrap().push(out_asset),
+                    None => continue,
+                }
+            }
+            Err(error) => {
+                println!(
+                    "Failed deleting overlapping vertices for {:?}: {error}",
+                    model_file
+                );
+                failures.lock().unwrap().push(AssetFailure {
+                    path: model_file,
+                    error,
+                });
+                continue;
+            }
         }
 
-        model.do_delete_vertices();
-
-        let mut results_lock = results.lock().unwrap();
-        results_lock.push(OutAsset::Asset(model));
-
         let duration = (Instant::now() - start_time).as_millis();
 
         println!(
@@ -129,7 +556,13 @@ fn mark_and_delete_vertices_worker(
     }
 }
 
-fn write_to_folder_worker(out_assets: Arc<Mutex<Vec<OutAsset>>>, dest_folder: &OsString) {
+fn write_to_folder_worker(
+    out_assets: Arc<Mutex<Vec<OutAsset>>>,
+    dest_folder: &OsString,
+    options: WriteOptions,
+    write_times: Arc<Mutex<Vec<(OsString, Duration)>>>,
+    failures: Arc<Mutex<Vec<AssetFailure>>>,
+) {
     loop {
         let out_asset = {
             let mut lock = out_assets.lock().unwrap();
@@ -139,17 +572,154 @@ fn write_to_folder_worker(out_assets: Arc<Mutex<Vec<OutAsset>>>, dest_folder: &O
                 None => return,
             }
         };
-        out_asset.write_to_folder(dest_folder);
+
+        let start_time = Instant::now();
+        let path = match &out_asset {
+            OutAsset::Asset(model) => model.source_file.clone(),
+            OutAsset::AssetRef(model_ref) => model_ref.source_file.clone(),
+        };
+
+        match out_asset.write_to_folder(dest_folder, &options) {
+            Ok(()) => write_times
+                .lock()
+                .unwrap()
+                .push((path, Instant::now() - start_time)),
+            Err(error) => {
+                println!("Failed writing {path:?}: {error}");
+                failures.lock().unwrap().push(AssetFailure { path, error });
+            }
+        }
     }
 }
 
+/// Moves the collected failures to the report
+fn drain_failures(failures: Arc<Mutex<Vec<AssetFailure>>>, report: &mut Report) {
+    let mut failures_lock = failures.lock().unwrap();
+    report.failed_assets.append(&mut failures_lock);
+}
+
 impl WorldAssets {
-    pub fn new(normal_asset_folder: OsString, hq_asset_folders: Vec<OsString>) -> Self {
+    pub fn new(config: &Config) -> Result<Self, CleanerError> {
+        let filter = AssetFilter::new(&config.include, &config.exclude)?;
+
+        let mut layers = vec![];
+        let mut scanned_layers = vec![];
+        for layer in config.asset_layers() {
+            let mut files = vec![];
+            let mut scanned_folders = vec![];
+            for folder in layer.folders.iter() {
+                let folder_files = crate::io::scan_folder_for_assets(folder, &filter)?;
+                files.extend(folder_files.iter().cloned());
+                scanned_folders.push((folder.clone(), folder_files));
+            }
+            scanned_layers.push(scanned_folders);
+            layers.push((layer, files));
+        }
+
+        // Output mirrors the folder structure under each input folder. The
+        // lowest layer is planned first so that cleaned assets keep their
+        // names.
+        let scanned_folders = scanned_layers
+            .into_iter()
+            .rev()
+            .flatten()
+            .collect::<Vec<_>>();
+        let out_paths = plan_out_paths(
+            &scanned_folders,
+            config.collision_mode,
+            config.output_format,
+        );
+
         let num_os_threads: usize = match std::thread::available_parallelism() {
             Ok(num_cpus) => num_cpus.into(),
             Err(_) => 1,
         };
 
+        let mut assets = Self {
+            hq_asset_files: vec![],
+            normal_assets: Arc::new(vec![]),
+            layers,
+            layer: match config.hq_tie_break {
+                Some(_) => 0,
+                None => 1,
+            },
+            hq_ranks: None,
+            out_assets: vec![],
+            out_paths,
+            num_threads: num_os_threads,
+            config: Arc::new(config.clone()),
+            report: Report::default(),
+        };
+
+        assets.load_layer();
+
+        Ok(assets)
+    }
+
+    /// Moves on to cleaning the next layer against the layers above it.
+    /// Returns false when all layers are done.
+    pub fn next_layer(&mut self) -> bool {
+        if self.layer + 1 >= self.layers.len() {
+            return false;
+        }
+        self.layer += 1;
+        self.load_layer();
+        true
+    }
+
+    /// Loads the assets of the current layer as the normal assets
+    fn load_layer(&mut self) {
+        let Some((layer, normal_asset_files)) = self.layers.get(self.layer).cloned() else {
+            return;
+        };
+        let files = normal_asset_files
+            .iter()
+            .map(|file| {
+                (
+                    file.clone(),
+                    self.config.texture_downscale_factor(file, &layer),
+                )
+            })
+            .collect();
+
+        // The top layer is its own hq assets when cleaned against itself
+        let index_grid_cell_size = match self.layer {
+            0 => Some(self.config.grid_cell_size),
+            _ => None,
+        };
+        let (normal_assets, mut failed_assets) = self.load_models(files, index_grid_cell_size);
+        let normal_assets = normal_assets
+            .into_iter()
+            .map(|model| Arc::new(RwLock::new(model)))
+            .collect::<Vec<_>>();
+
+        self.report.failed_assets.append(&mut failed_assets);
+        self.hq_ranks = match (self.layer, self.config.hq_tie_break) {
+            (0, Some(tie_break)) => {
+                self.report.hq_asset_cnt = normal_assets.len();
+                Some(rank_hq_assets(
+                    &normal_asset_files,
+                    &normal_assets,
+                    tie_break,
+                ))
+            }
+            _ => {
+                self.report.normal_asset_cnt += normal_assets.len();
+                None
+            }
+        };
+        self.normal_assets = Arc::new(normal_assets);
+    }
+
+    /// Loads files, given with their texture downscale factors, in parallel.
+    /// Index grids are built when index_grid_cell_size is given.
+    fn load_models(
+        &self,
+        files: Vec<(OsString, u32)>,
+        index_grid_cell_size: Option<GridCellSize>,
+    ) -> (Vec<Model>, Vec<AssetFailure>) {
+        let num_os_threads = self.num_threads;
+
         // Create a channel for sending tasks to workers.
         let (tx_task, rx_task) = mpsc::channel::<crate::messages::ModelLoadTask>();
         let receiver_guard_task = Arc::new(Mutex::new(rx_task));
@@ -157,7 +727,6 @@ impl WorldAssets {
         // Create a channel for workers to send responses.
         let (tx_resp, rx_resp) = mpsc::channel::<crate::messages::ModelLoadTaskResponse>();
 
-        // Load all normal assets to permanent memory
         // Spawn worker threads
         let mut workers = Vec::new();
         for _ in 0..num_os_threads {
@@ -168,7 +737,17 @@ impl WorldAssets {
         }
         let mut num_running = num_os_threads;
 
-        crate::io::scan_folder_and_create_tasks(&normal_asset_folder, &tx_task);
+        for (path, texture_downscale_factor) in files {
+            tx_task
+                .send(crate::messages::ModelLoadTask::Task(
+                    crate::messages::TaskContainer {
+                        path,
+                        texture_downscale_factor,
+                        index_grid_cell_size,
+                    },
+                ))
+                .expect("Error while sending task");
+        }
 
         // Create tasks to terminate workers
         for _ in 0..num_os_threads {
@@ -177,47 +756,128 @@ impl WorldAssets {
                 .expect("Failed to send task");
         }
 
-        let mut normal_assets = vec![];
-        let mut hq_asset_files = vec![];
-
-        for hq_asset_folder in hq_asset_folders {
-            for f in crate::io::scan_folder_for_objs(&hq_asset_folder) {
-                hq_asset_files.push(f);
-            }
-        }
+        let mut models = vec![];
+        let mut failed_assets = vec![];
 
         // Collect responses
         while num_running > 0 {
             let resp = rx_resp.recv().unwrap();
             match resp {
                 crate::messages::ModelLoadTaskResponse::Model(model_resp) => {
-                    normal_assets.push(Arc::new(RwLock::new(model_resp.model)));
+                    let mut model = *model_resp.model;
+                    if let Some(out_path) = self.out_paths.get(&model.source_file) {
+                        model.out_path = out_path.clone();
+                    }
+                    models.push(model);
+                }
+                crate::messages::ModelLoadTaskResponse::Failed(failed) => {
+                    failed_assets.push(AssetFailure {
+                        path: failed.path,
+                        error: failed.error,
+                    });
                 }
                 crate::messages::ModelLoadTaskResponse::Terminated => num_running -= 1,
             }
         }
 
-        Self {
-            hq_asset_files,
-            normal_assets: Arc::new(normal_assets),
-            out_assets: vec![],
-            num_threads: num_os_threads,
+        (models, failed_assets)
+    }
+
+    /// Hq sources of the current layer: the top layer as it is until it is
+    /// cleaned, and the output of the cleaned layers above. Cleaned assets
+    /// are moved out of the out assets until they are handed back.
+    fn take_hq_sources(&mut self) -> Vec<HqSource> {
+        let top_layer_cleaned = self.config.hq_tie_break.is_some();
+        if self.layer == 0 {
+            return (0..self.normal_assets.len())
+                .map(HqSource::Normal)
+                .collect();
+        }
+
+        let mut hq_sources = vec![];
+        if self.layer == 1
+            && !top_layer_cleaned
+            && let Some((layer, files)) = self.layers.first()
+        {
+            hq_sources.extend(files.iter().map(|file| {
+                let factor = self.config.texture_downscale_factor(file, layer);
+                HqSource::File(file.clone(), factor)
+            }));
         }
+
+        // Copied assets are the same as their files
+        let mut out_assets = vec![];
+        for out_asset in std::mem::take(&mut self.out_assets) {
+            match out_asset {
+                OutAsset::Asset(model) => hq_sources.push(HqSource::Cleaned(Box::new(model))),
+                OutAsset::AssetRef(model_ref) => {
+                    hq_sources.push(HqSource::File(
+                        model_ref.source_file.clone(),
+                        model_ref.texture_downscale_factor,
+                    ));
+                    out_assets.push(OutAsset::AssetRef(model_ref));
+                }
+            }
+        }
+        self.out_assets = out_assets;
+        hq_sources
     }
 
     pub fn process_overlaps(&mut self) {
-        let process_queue = Arc::new(Mutex::new(self.hq_asset_files.clone()));
+        let pass = Arc::new(OverlapPass {
+            threshold: match self.layers.get(self.layer) {
+                Some((layer, _)) => layer.overlap_threshold,
+                None => self.config.overlap_threshold,
+            },
+            ranks: self.hq_ranks.take(),
+        });
+
+        let hq_sources = self.take_hq_sources();
+        // The top layer is counted as it is loaded in its first pass
+        let top_layer_streamed = self.layer == 1 && self.config.hq_tie_break.is_none();
+        self.hq_asset_files = hq_sources
+            .iter()
+            .map(|hq_source| match hq_source {
+                HqSource::File(path, _) => path.clone(),
+                HqSource::Cleaned(model) => model.source_file.clone(),
+                HqSource::Normal(idx) => {
+                    self.normal_assets[*idx].read().unwrap().source_file.clone()
+                }
+            })
+            .collect();
+
+        let process_queue = Arc::new(Mutex::new(hq_sources));
         let hq_asset_references: Arc<Mutex<Vec<ModelReference>>> = Arc::new(Mutex::new(Vec::new()));
+        let cleaned_assets = Arc::new(Mutex::new(Vec::new()));
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let failures = Arc::new(Mutex::new(Vec::new()));
 
         let mut workers = vec![];
 
         for _ in 0..self.num_threads {
             let normal_assets = self.normal_assets.clone();
-            let hq_assets = process_queue.clone();
+            let hq_sources = process_queue.clone();
             let hq_asset_references_clone = hq_asset_references.clone();
+            let cleaned_assets_clone = cleaned_assets.clone();
+            let found_clone = found.clone();
+            let summaries_clone = summaries.clone();
+            let failures_clone = failures.clone();
+            let pass_clone = pass.clone();
+            let config = self.config.clone();
 
             workers.push(thread::spawn(move || {
-                hq_asset_worker(hq_assets, normal_assets, hq_asset_references_clone)
+                hq_asset_worker(
+                    hq_sources,
+                    normal_assets,
+                    hq_asset_references_clone,
+                    cleaned_assets_clone,
+                    found_clone,
+                    summaries_clone,
+                    failures_clone,
+                    pass_clone,
+                    config,
+                )
             }));
         }
 
@@ -226,9 +886,67 @@ impl WorldAssets {
             t.join().expect("Failed to join thread");
         }
 
-        let mut hq_asset_references_lock = hq_asset_references.lock().unwrap();
+        drain_failures(failures, &mut self.report);
+
+        // The top layer was only read while cleaned against itself
+        for (normal_idx, hq_asset_name, overlaps) in found.lock().unwrap().drain(..) {
+            let mut asset = self.normal_assets[normal_idx].write().unwrap();
+            add_overlaps(&mut asset, &hq_asset_name, &overlaps);
+        }
+        if self.layer == 0 {
+            for asset in self.normal_assets.iter() {
+                asset.write().unwrap().drop_index_grids();
+            }
+        }
+
+        let mut cleaned_assets = cleaned_assets.lock().unwrap();
         self.out_assets
-            .extend(hq_asset_references_lock.drain(..).map(OutAsset::AssetRef));
+            .extend(cleaned_assets.drain(..).map(OutAsset::Asset));
+
+        let mut summaries = summaries.lock().unwrap();
+        if top_layer_streamed {
+            self.report.hq_asset_cnt = summaries.len();
+        }
+
+        // Assets of the layers below the top one were summarised and written
+        // when their own layer was cleaned, and the top layer ones by the
+        // first pass. When the top layer is cleaned against itself, its
+        // assets are summarised and written as cleaned assets instead.
+        let mut new_assets = HashSet::new();
+        for summary in summaries.drain(..) {
+            match self
+                .report
+                .asset_summaries
+                .iter_mut()
+                .find(|s| s.path == summary.path)
+            {
+                Some(existing) if existing.role == AssetRole::Hq => {
+                    existing
+                        .overlapping_assets
+                        .extend(summary.overlapping_assets);
+                    existing.overlapping_assets.sort();
+                }
+                Some(_) => {}
+                None if pass.ranks.is_some() => {}
+                None => {
+                    new_assets.insert(summary.path.clone());
+                    self.report.asset_summaries.push(summary);
+                }
+            }
+        }
+
+        let mut hq_asset_references_lock = hq_asset_references.lock().unwrap();
+        for hq_asset_ref in hq_asset_references_lock.iter_mut() {
+            if let Some(out_path) = self.out_paths.get(&hq_asset_ref.source_file) {
+                hq_asset_ref.out_path = out_path.clone();
+            }
+        }
+        self.out_assets.extend(
+            hq_asset_references_lock
+                .drain(..)
+                .filter(|hq_asset_ref| new_assets.contains(&hq_asset_ref.source_file))
+                .map(OutAsset::AssetRef),
+        );
 
         for hq_asset in self.hq_asset_files.iter() {
             println!("Threads done, {hq_asset:?}");
@@ -237,16 +955,32 @@ impl WorldAssets {
 
     pub fn mark_and_delete_vertices(&mut self) {
         let mut models = Vec::new();
+        let mut deleted_assets = HashSet::new();
         let results: Arc<Mutex<Vec<OutAsset>>> = Arc::new(Mutex::new(Vec::new()));
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let failures = Arc::new(Mutex::new(Vec::new()));
 
         let normal_assets = Arc::try_unwrap(std::mem::take(&mut self.normal_assets)).unwrap();
 
+        // Assets that failed against some hq asset are only partly cleaned
+        // and are left out of the output
+        let failed = self
+            .report
+            .failed_assets
+            .iter()
+            .map(|failure| failure.path.clone())
+            .collect::<HashSet<_>>();
         for model_guarded in normal_assets {
             let model = Arc::try_unwrap(model_guarded)
                 .expect("Still references")
                 .into_inner()
                 .unwrap();
 
+            if failed.contains(&model.source_file) {
+                println!("Skipping {:?}, it failed processing", model.source_file);
+                continue;
+            }
+            deleted_assets.insert(model.source_file.clone());
             models.push(model);
         }
 
@@ -256,8 +990,17 @@ impl WorldAssets {
         for _ in 0..self.num_threads {
             let task_queue_clone = task_queue.clone();
             let results_clone = results.clone();
+            let summaries_clone = summaries.clone();
+            let failures_clone = failures.clone();
+            let config = self.config.clone();
             handles.push(thread::spawn(move || {
-                mark_and_delete_vertices_worker(task_queue_clone, results_clone);
+                mark_and_delete_vertices_worker(
+                    task_queue_clone,
+                    results_clone,
+                    summaries_clone,
+                    failures_clone,
+                    config,
+                );
             }))
         }
 
@@ -265,8 +1008,41 @@ impl WorldAssets {
             h.join().expect("Failed to join thread");
         }
 
+        drain_failures(failures, &mut self.report);
+
+        let mut summaries = summaries.lock().unwrap();
+        for summary in summaries.iter_mut() {
+            summary.layer = self.layer;
+            if self.layer == 0 {
+                summary.role = AssetRole::Hq;
+            }
+        }
+        self.report.asset_summaries.append(&mut summaries);
+
         let mut results_unguarded = Arc::try_unwrap(results).unwrap().into_inner().unwrap();
 
+        // Whatever didn't produce an out asset was fully overlapping
+        for out_asset in results_unguarded.iter() {
+            let source_file = match out_asset {
+                OutAsset::AssetRef(model_ref) => {
+                    self.report
+                        .copied_assets
+                        .push(model_ref.source_file.clone());
+                    &model_ref.source_file
+                }
+                OutAsset::Asset(model) => {
+                    self.report.rewritten_assets.push(model.source_file.clone());
+                    &model.source_file
+                }
+            };
+            deleted_assets.remove(source_file);
+        }
+        for failure in self.report.failed_assets.iter() {
+            deleted_assets.remove(&failure.path);
+        }
+        self.report.deleted_assets.extend(deleted_assets);
+
+        // The layers below are cleaned against this one as it is written
         self.out_assets.append(&mut results_unguarded);
 
         println!("Deleted all overlapping vertices");
@@ -274,21 +1050,257 @@ impl WorldAssets {
 
     pub fn write_to_folder(&mut self, dest: &OsString) {
         println!("Writing results to: {:?}", dest);
-        let out_assets = std::mem::take(&mut self.out_assets);
+        let mut out_assets = std::mem::take(&mut self.out_assets);
+        plan_textures(&mut out_assets, self.config.texture_format);
 
         let mut handles = Vec::new();
         let tasks = Arc::new(Mutex::new(out_assets));
+        let write_times = Arc::new(Mutex::new(Vec::new()));
+        let failures = Arc::new(Mutex::new(Vec::new()));
 
         for _ in 0..self.num_threads {
             let tasks_clone = tasks.clone();
             let dest_clone = dest.clone();
+            let write_times_clone = write_times.clone();
+            let failures_clone = failures.clone();
+            let options = self.config.write_options();
             handles.push(thread::spawn(move || {
-                write_to_folder_worker(tasks_clone, &dest_clone);
+                write_to_folder_worker(
+                    tasks_clone,
+                    &dest_clone,
+                    options,
+                    write_times_clone,
+                    failures_clone,
+                );
             }));
         }
 
         for h in handles {
             h.join().expect("Failed to join thread");
         }
+
+        drain_failures(failures, &mut self.report);
+
+        let write_times = write_times
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect::<HashMap<_, _>>();
+        for summary in self.report.asset_summaries.iter_mut() {
+            if let Some(write_time) = write_times.get(&summary.path) {
+                summary.write_time = *write_time;
+            }
+        }
+    }
+
+    /// Consumes the assets, returning the summary of the run so far
+    pub fn into_report(mut self) -> Report {
+        self.report
+            .asset_summaries
+            .sort_by(|a, b| (a.layer, a.role, &a.path).cmp(&(b.layer, b.role, &b.path)));
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{IslandMode, IslandThreshold},
+        test_utils::{TempDir, test_config, write_obj},
+    };
+
+    #[test]
+    fn test_rank_hq_assets_by_capture_date() {
+        let dir = TempDir::new("capture_date");
+        let files = ["a.obj", "b.obj", "c.obj", "d.obj", "e.obj"]
+            .map(|file| dir.join(file).into_os_string())
+            .to_vec();
+        let write_sidecar = |file: &str, json: &str| {
+            std::fs::write(dir.join(format!("{file}.json")), json).unwrap()
+        };
+        write_sidecar("b.obj", r#"{"capture_date": "2023-05-01"}"#);
+        write_sidecar("c.obj", r#"{"capture_date": "2024-01-15"}"#);
+        write_sidecar("d.obj", r#"{"captured": "2025-01-01"}"#);
+        // Later than b as a string, but not a valid date
+        write_sidecar("e.obj", r#"{"capture_date": "2023-5-1"}"#);
+
+        let by_date = rank_hq_assets(&files, &[], HqTieBreak::CaptureDate);
+        let by_folder = rank_hq_assets(&files, &[], HqTieBreak::FolderOrder);
+
+        // Assets without a valid date keep their folder order after the
+        // dated ones
+        let ranks =
+            |ranks: &HashMap<OsString, usize>| files.iter().map(|f| ranks[f]).collect::<Vec<_>>();
+        assert_eq!(ranks(&by_date), [2, 1, 0, 3, 4]);
+        assert_eq!(ranks(&by_folder), [0, 1, 2, 3, 4]);
+    }
+
+    /// Cleans the top layer against itself for two hq assets that fully
+    /// overlap each other, a square and the same square just above it
+    fn clean_overlapping_hq_assets(dir: &TempDir, ranks: Option<[usize; 2]>) -> Report {
+        let square = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [10.0, 10.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [10.0, 10.0, 0.0],
+            [0.0, 10.0, 0.0],
+        ];
+        write_obj(&dir.join("hq/a.obj"), &[("a", &square)]);
+        write_obj(
+            &dir.join("hq/b.obj"),
+            &[("b", &square.map(|[x, y, z]| [x, y, z + 0.05]))],
+        );
+        std::fs::create_dir_all(dir.join("normal")).unwrap();
+        let config = Config {
+            hq_tie_break: Some(HqTieBreak::FolderOrder),
+            dry_run: true,
+            ..test_config(dir)
+        };
+
+        let mut assets = WorldAssets::new(&config).unwrap();
+        if let Some(ranks) = ranks {
+            assets.hq_ranks = Some(HashMap::from([
+                (dir.join("hq/a.obj").into_os_string(), ranks[0]),
+                (dir.join("hq/b.obj").into_os_string(), ranks[1]),
+            ]));
+        }
+        // The top layer is loaded once, with the index grids of the hq side
+        // until it is processed
+        let grid_cnt = |assets: &WorldAssets| {
+            let assets = assets
+                .normal_assets
+                .iter()
+                .map(|asset| asset.read().unwrap());
+            assets
+                .map(|asset| {
+                    asset
+                        .meshes
+                        .iter()
+                        .filter(|m| m.index_grid().is_some())
+                        .count()
+                })
+                .sum::<usize>()
+        };
+        assert_eq!(assets.normal_assets.len(), 2);
+        assert_eq!(grid_cnt(&assets), 2);
+        assets.process_overlaps();
+        assert_eq!(assets.hq_asset_files.len(), 2);
+        assert_eq!(grid_cnt(&assets), 0);
+        assets.mark_and_delete_vertices();
+        assets.into_report()
+    }
+
+    #[test]
+    fn test_hq_assets_cleaned_by_rank() {
+        let dir = TempDir::new("hq_ranks");
+        let report = clean_overlapping_hq_assets(&dir, None);
+
+        let a = dir.join("hq/a.obj").into_os_string();
+        let b = dir.join("hq/b.obj").into_os_string();
+        assert_eq!(report.deleted_assets.len(), 1);
+        assert_eq!(report.deleted_assets[0], b);
+        let summary = |path: &OsString| {
+            report
+                .asset_summaries
+                .iter()
+                .find(|s| &s.path == path)
+                .unwrap()
+        };
+        // Only b, ranked below a, loses the overlap
+        assert_eq!(summary(&a).outcome, AssetOutcome::Copied);
+        assert_eq!(summary(&a).triangles_after, 2);
+        assert_eq!(summary(&b).outcome, AssetOutcome::Deleted);
+        assert_eq!(summary(&b).overlapping_assets, [a]);
+    }
+
+    #[test]
+    fn test_hq_assets_with_equal_ranks_kept() {
+        let dir = TempDir::new("hq_equal_ranks");
+        let report = clean_overlapping_hq_assets(&dir, Some([0, 0]));
+
+        assert!(report.deleted_assets.is_empty());
+        assert_eq!(report.asset_summaries.len(), 2);
+        for summary in report.asset_summaries.iter() {
+            assert_eq!(summary.outcome, AssetOutcome::Copied);
+            assert_eq!(summary.triangles_after, 2);
+            assert!(summary.overlapping_assets.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_failed_assets_not_counted_or_written() {
+        let dir = TempDir::new("failed_assets");
+        write_obj(
+            &dir.join("hq/hq.obj"),
+            &[("hq", &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]])],
+        );
+        std::fs::write(dir.join("hq/empty.obj"), "v 0 0 0\n").unwrap();
+        write_obj(
+            &dir.join("normal/a.obj"),
+            &[("a", &[[5.0, 0.0, 0.0], [6.0, 0.0, 0.0], [6.0, 1.0, 0.0]])],
+        );
+        write_obj(
+            &dir.join("normal/b.obj"),
+            &[("b", &[[8.0, 0.0, 0.0], [9.0, 0.0, 0.0], [9.0, 1.0, 0.0]])],
+        );
+
+        let mut assets = WorldAssets::new(&test_config(&dir)).unwrap();
+        assets.process_overlaps();
+        assert_eq!(assets.report.hq_asset_cnt, 1);
+        // As if b had failed against one of the hq assets
+        let b = dir.join("normal/b.obj").into_os_string();
+        assets.report.failed_assets.push(AssetFailure {
+            path: b.clone(),
+            error: CleanerError::MissingMeshData("index grid"),
+        });
+        assets.mark_and_delete_vertices();
+
+        let out_files = assets
+            .out_assets
+            .iter()
+            .map(|out_asset| match out_asset {
+                OutAsset::Asset(model) => model.source_file.clone(),
+                OutAsset::AssetRef(model_ref) => model_ref.source_file.clone(),
+            })
+            .collect::<Vec<_>>();
+        assert!(out_files.contains(&dir.join("normal/a.obj").into_os_string()));
+        assert!(!out_files.contains(&b));
+        let report = assets.into_report();
+        assert!(!report.copied_assets.contains(&b));
+        assert!(!report.deleted_assets.contains(&b));
+        assert!(report.asset_summaries.iter().all(|s| s.path != b));
+    }
+
+    #[test]
+    fn test_islands_deleted_in_vertices_mode() {
+        let dir = TempDir::new("islands");
+        let path = dir.join("tile.obj");
+        // A quad and a separate triangle
+        std::fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 5 0 0\nv 6 0 0\nv 6 1 0\n\
+             f 1 2 3\nf 1 3 4\nf 5 6 7\n",
+        )
+        .unwrap();
+        let mut model = Model::try_new_from_file(path.into(), false, None, 1).unwrap();
+        // Vertices 2 and 3 of the quad overlap, leaving 0 and 1 as an island
+        // of two
+        model.meshes[0].overlapping_vertice_idxs.extend([2, 3]);
+        let config = Config {
+            island_threshold: IslandThreshold {
+                mode: IslandMode::VertexCount,
+                value: 3.0,
+            },
+            ..test_config(&dir)
+        };
+
+        let (out_asset, summary) = mark_and_delete_model_vertices(model, &config).unwrap();
+
+        assert_eq!(summary.outcome, AssetOutcome::Rewritten);
+        assert_eq!(summary.vertices_after, 3);
+        assert_eq!(summary.triangles_after, 1);
+        assert!(matches!(out_asset, Some(OutAsset::Asset(_))));
     }
 }